@@ -0,0 +1,654 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use image::io::Reader;
+use image::{AnimationDecoder, DynamicImage, Frame, ImageFormat, RgbaImage};
+use log::warn;
+use serde::ser::SerializeMap;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::config::color;
+use crate::config::palette::expand_home;
+
+/// How a background image is scaled to an output.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScaleMode {
+    /// Scale to cover the output, cropping the overflow
+    Fill,
+    /// Scale to fit the output, showing `color_bg` around it
+    Fit,
+    /// Center the image without scaling
+    Center,
+    /// Repeat the image without scaling, starting in the top left corner
+    Tile,
+    /// Scale to the size of the output, ignoring the aspect ratio
+    Stretch,
+}
+
+impl FromStr for ScaleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fill" => Ok(ScaleMode::Fill),
+            "fit" => Ok(ScaleMode::Fit),
+            "center" => Ok(ScaleMode::Center),
+            "tile" => Ok(ScaleMode::Tile),
+            "stretch" => Ok(ScaleMode::Stretch),
+            _ => Err(format!("expected fill, fit, center, tile or stretch, got \"{}\"", s)),
+        }
+    }
+}
+
+impl fmt::Display for ScaleMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ScaleMode::Fill => "fill",
+            ScaleMode::Fit => "fit",
+            ScaleMode::Center => "center",
+            ScaleMode::Tile => "tile",
+            ScaleMode::Stretch => "stretch",
+        })
+    }
+}
+
+/// An effect applied to the background, after it is scaled to an output. Written as a table with
+/// a single key, like `{ blur = 8 }`, or as `"blur:8"`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    /// Box blur with the given radius in pixels
+    Blur(u32),
+    /// Gaussian blur with the given standard deviation in pixels, approximated by box blurs
+    GaussianBlur(u32),
+    /// Average squares of the given size in pixels
+    Pixelate(u32),
+    /// Darken by the given fraction, from 0 to 1
+    Darken(f64),
+    /// Brighten by the given fraction from 0 to 1, or darken from 0 to -1
+    Brightness(f64),
+    /// Scale the contrast by the given factor, 1 leaves it unchanged
+    Contrast(f64),
+    /// Desaturate by the given fraction, 1 leaves shades of gray
+    Grayscale(f64),
+    /// Darken the corners by the given fraction, from 0 to 1
+    Vignette(f64),
+    /// Blend a color over the background by its alpha
+    Tint(u32),
+    /// Add random noise of up to the given fraction of the full range, from 0 to 1
+    Noise(f64),
+}
+
+impl FromStr for Effect {
+    type Err = String;
+
+    /// Parse the `name:value` form used on the command line.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) =
+            s.split_once(':').ok_or_else(|| format!("expected NAME:VALUE, got \"{}\"", s))?;
+        let pixels = || value.parse::<u32>().map_err(|err| format!("{}: {}", s, err));
+        let number = |range: RangeInclusive<f64>, expected: &str| match value.parse::<f64>() {
+            Ok(number) if range.contains(&number) => Ok(number),
+            _ => Err(format!("{}: expected {}", s, expected)),
+        };
+        let fraction = || number(0.0..=1.0, "a fraction from 0 to 1");
+        match name {
+            "blur" => Ok(Effect::Blur(pixels()?)),
+            "gaussian_blur" => Ok(Effect::GaussianBlur(pixels()?)),
+            "pixelate" => Ok(Effect::Pixelate(pixels()?)),
+            "darken" => Ok(Effect::Darken(fraction()?)),
+            "brightness" => Ok(Effect::Brightness(number(-1.0..=1.0, "a fraction from -1 to 1")?)),
+            "contrast" => Ok(Effect::Contrast(number(0.0..=f64::MAX, "a factor of at least 0")?)),
+            "grayscale" => Ok(Effect::Grayscale(fraction()?)),
+            "vignette" => Ok(Effect::Vignette(fraction()?)),
+            "tint" => match color::from_str(value) {
+                Ok(color) => Ok(Effect::Tint(color)),
+                Err(err) => Err(format!("{}: {}", s, err)),
+            },
+            "noise" => Ok(Effect::Noise(fraction()?)),
+            _ => Err(format!(
+                "unknown effect \"{}\", expected blur, gaussian_blur, pixelate, darken, \
+                 brightness, contrast, grayscale, vignette, tint or noise",
+                name
+            )),
+        }
+    }
+}
+
+// toml can't (de)serialize enum variants with a value, so they are converted to tables by hand
+impl<'de> Deserialize<'de> for Effect {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Short(String),
+            Table(BTreeMap<String, Value>),
+        }
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Number(f64),
+            Text(String),
+        }
+        let short = match Raw::deserialize(deserializer)? {
+            Raw::Short(short) => short,
+            Raw::Table(table) if table.len() == 1 => match table.into_iter().next().unwrap() {
+                (name, Value::Number(number)) => format!("{}:{}", name, number),
+                (name, Value::Text(text)) => format!("{}:{}", name, text),
+            },
+            Raw::Table(_) => {
+                return Err(de::Error::custom("expected a table with a single effect"))
+            }
+        };
+        short.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Effect {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self {
+            Effect::Blur(radius) => map.serialize_entry("blur", radius)?,
+            Effect::GaussianBlur(sigma) => map.serialize_entry("gaussian_blur", sigma)?,
+            Effect::Pixelate(size) => map.serialize_entry("pixelate", size)?,
+            Effect::Darken(amount) => map.serialize_entry("darken", amount)?,
+            Effect::Brightness(amount) => map.serialize_entry("brightness", amount)?,
+            Effect::Contrast(factor) => map.serialize_entry("contrast", factor)?,
+            Effect::Grayscale(amount) => map.serialize_entry("grayscale", amount)?,
+            Effect::Vignette(strength) => map.serialize_entry("vignette", strength)?,
+            Effect::Tint(color) => map.serialize_entry("tint", &color::to_string(*color))?,
+            Effect::Noise(amount) => map.serialize_entry("noise", amount)?,
+        }
+        map.end()
+    }
+}
+
+/// Whether a gradient runs along a line or outwards from the center of the output.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GradientKind {
+    Linear,
+    Radial,
+}
+
+/// A color of a gradient at a position from 0 to 1 along it. Written as `"COLOR"` or
+/// `"COLOR POSITION%"`, stops without a position are spread evenly between their neighbors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stop {
+    pub color: u32,
+    pub position: Option<f64>,
+}
+
+/// A gradient drawn over `color_bg`. Colors with alpha are blended over it.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Gradient {
+    #[serde(rename = "type")]
+    pub kind: GradientKind,
+    /// Direction of a linear gradient in degrees, clockwise from upwards. 180 by default, from
+    /// top to bottom.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub angle: Option<f64>,
+    pub stops: Vec<Stop>,
+}
+
+/// A repeating pattern drawn over `color_bg` and the gradient.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PatternKind {
+    /// Squares of `size` pixels
+    Checker,
+    /// Diagonal stripes, `size` pixels wide along a row
+    Stripes,
+    /// Dots with a distance of `size` pixels
+    Dots,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pattern {
+    #[serde(rename = "type")]
+    pub kind: PatternKind,
+    pub size: u32,
+    /// Blended over the background by its alpha
+    #[serde(deserialize_with = "deserialize_color", serialize_with = "serialize_color")]
+    pub color: u32,
+}
+
+impl Gradient {
+    /// The colors of the stops with their positions, which never decrease.
+    pub fn positions(&self) -> Vec<(f64, u32)> {
+        let last = self.stops.len().saturating_sub(1);
+        let mut positions: Vec<_> = self
+            .stops
+            .iter()
+            .enumerate()
+            .map(|(i, stop)| match stop.position {
+                Some(position) => Some(position),
+                None if i == 0 => Some(0.0),
+                None if i == last => Some(1.0),
+                None => None,
+            })
+            .collect();
+
+        let mut previous = (0, 0.0);
+        for i in 0..positions.len() {
+            let position = match positions[i] {
+                Some(position) => position.max(previous.1),
+                None => continue,
+            };
+            // Spread the stops since the previous position evenly up to this one
+            let (start, from) = previous;
+            let step = (position - from) / i.saturating_sub(start).max(1) as f64;
+            for (j, between) in positions.iter_mut().enumerate().take(i).skip(start + 1) {
+                *between = Some(from + step * (j - start) as f64);
+            }
+            positions[i] = Some(position);
+            previous = (i, position);
+        }
+        positions.into_iter().flatten().zip(self.stops.iter().map(|stop| stop.color)).collect()
+    }
+}
+
+impl FromStr for Stop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let parse_color = |s: &str| color::from_str(s).map_err(|err| format!("{}: {}", s, err));
+        match s.rsplit_once(' ') {
+            Some((color, position)) if position.ends_with('%') => {
+                let position = position[..position.len() - 1]
+                    .parse::<f64>()
+                    .map_err(|err| format!("{}: {}", s, err))?;
+                Ok(Stop { color: parse_color(color)?, position: Some(position / 100.0) })
+            }
+            _ => Ok(Stop { color: parse_color(s)?, position: None }),
+        }
+    }
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&color::to_string(self.color))?;
+        match self.position {
+            Some(position) => write!(f, " {}%", position * 100.0),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Stop {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Stop {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl FromStr for Gradient {
+    type Err = String;
+
+    /// Parse the `linear[:ANGLE]:STOPS` or `radial:STOPS` form used on the command line, with
+    /// stops separated by commas.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, rest) =
+            s.split_once(':').ok_or_else(|| format!("expected TYPE:STOPS, got \"{}\"", s))?;
+        let kind = match kind {
+            "linear" => GradientKind::Linear,
+            "radial" => GradientKind::Radial,
+            _ => return Err(format!("unknown gradient \"{}\", expected linear or radial", kind)),
+        };
+        let (angle, stops) = match rest.split_once(':') {
+            Some((angle, stops)) if kind == GradientKind::Linear => {
+                (Some(angle.parse::<f64>().map_err(|err| format!("{}: {}", angle, err))?), stops)
+            }
+            _ => (None, rest),
+        };
+        let stops = split_list(stops).map(str::parse).collect::<Result<_, _>>()?;
+        Ok(Gradient { kind, angle, stops })
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    /// Parse the `TYPE:SIZE:COLOR` form used on the command line.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        let (kind, size, color) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(kind), Some(size), Some(color)) => (kind, size, color),
+            _ => return Err(format!("expected TYPE:SIZE:COLOR, got \"{}\"", s)),
+        };
+        let kind = match kind {
+            "checker" => PatternKind::Checker,
+            "stripes" => PatternKind::Stripes,
+            "dots" => PatternKind::Dots,
+            _ => {
+                return Err(format!(
+                    "unknown pattern \"{}\", expected checker, stripes or dots",
+                    kind
+                ))
+            }
+        };
+        let size = size.parse().map_err(|err| format!("{}: {}", size, err))?;
+        let color = color::from_str(color).map_err(|err| format!("{}: {}", color, err))?;
+        Ok(Pattern { kind, size, color })
+    }
+}
+
+/// Split at commas outside of parentheses, which belong to colors like `rgb(0, 0, 0)`.
+fn split_list(s: &str) -> impl Iterator<Item = &str> {
+    let mut depth = 0;
+    s.split(move |c| {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        c == ',' && depth == 0
+    })
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    color::from_str(&s)
+        .map_err(|err| de::Error::custom(format!("invalid color \"{}\": {}", s, err)))
+}
+
+fn serialize_color<S: Serializer>(color: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&color::to_string(*color))
+}
+
+/// Whether `path` is a directory of images rather than an image. `~/` is expanded to the home
+/// directory.
+pub fn is_directory(path: &Path) -> bool {
+    expand_home(&path.to_string_lossy()).is_dir()
+}
+
+/// The PNG and JPEG images in a directory, sorted by name. Subdirectories are not searched.
+pub fn list_images(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut images = Vec::new();
+    for entry in fs::read_dir(expand_home(&dir.to_string_lossy()))? {
+        let path = entry?.path();
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let is_image = extension.is_some_and(|extension| {
+            ["png", "jpg", "jpeg"].iter().any(|known| extension.eq_ignore_ascii_case(known))
+        });
+        if is_image && path.is_file() {
+            images.push(path);
+        }
+    }
+    images.sort();
+    Ok(images)
+}
+
+/// List the images of a background directory, or log why there are none.
+pub fn load_directory(dir: &Path) -> Vec<PathBuf> {
+    match list_images(dir) {
+        Ok(images) if images.is_empty() => {
+            warn!("The background directory {} contains no PNG or JPEG images", dir.display());
+            images
+        }
+        Ok(images) => images,
+        Err(err) => {
+            warn!("Could not read the background directory {}: {}", dir.display(), err);
+            Vec::new()
+        }
+    }
+}
+
+/// Frames asking to be shown this long or shorter are shown for `DEFAULT_DELAY`, like browsers do.
+const MIN_DELAY: Duration = Duration::from_millis(10);
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// Every frame of an animation is kept in memory, once decoded and again at the size of each
+/// output. Animations taking more than this are shown as a still image.
+pub const MAX_ANIMATION_BYTES: usize = 256 << 20;
+
+/// Decode a PNG, JPEG or GIF image, with every frame if it is animated and how long each of them
+/// is shown. `~/` is expanded to the home directory.
+pub fn decode_frames(path: &Path) -> ImageResult<Vec<(RgbaImage, Duration)>> {
+    decode_frames_within(path, MAX_ANIMATION_BYTES)
+}
+
+/// Decode the frames of an image like `decode_frames`, only the first if all of them take more
+/// than `max_bytes`.
+fn decode_frames_within(path: &Path, max_bytes: usize) -> ImageResult<Vec<(RgbaImage, Duration)>> {
+    let expanded = expand_home(&path.to_string_lossy());
+    let reader = Reader::open(expanded)?.with_guessed_format()?;
+    let frames = match reader.format() {
+        Some(ImageFormat::Gif) => {
+            animation(GifDecoder::new(reader.into_inner())?, path, max_bytes)?
+        }
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(reader.into_inner())?;
+            if decoder.is_apng() {
+                animation(decoder.apng(), path, max_bytes)?
+            } else {
+                vec![(DynamicImage::from_decoder(decoder)?.into_rgba8(), Duration::ZERO)]
+            }
+        }
+        _ => vec![(reader.decode()?.into_rgba8(), Duration::ZERO)],
+    };
+    if frames.is_empty() {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::NoMoreData,
+        )));
+    }
+    Ok(frames)
+}
+
+fn animation<'a>(
+    decoder: impl AnimationDecoder<'a>,
+    path: &Path,
+    max_bytes: usize,
+) -> ImageResult<Vec<(RgbaImage, Duration)>> {
+    let mut frames = Vec::new();
+    let mut bytes = 0;
+    for frame in decoder.into_frames() {
+        let frame: Frame = frame?;
+        let delay = Duration::from(frame.delay());
+        let image = frame.into_buffer();
+        bytes += image.as_raw().len();
+        if bytes > max_bytes && !frames.is_empty() {
+            warn!(
+                "The animation {} takes more than {} MiB, showing its first frame only",
+                path.display(),
+                max_bytes >> 20
+            );
+            frames.truncate(1);
+            break;
+        }
+        frames.push((image, if delay <= MIN_DELAY { DEFAULT_DELAY } else { delay }));
+    }
+    Ok(frames)
+}
+
+/// Decode an image, the first frame if it is animated.
+pub fn decode(path: &Path) -> ImageResult<RgbaImage> {
+    Ok(decode_frames(path)?.swap_remove(0).0)
+}
+
+/// Decode the background image, or log why it can't be used. Without it `color_bg` is shown.
+pub fn load_image(path: &Path) -> Option<RgbaImage> {
+    load_frames(path).into_iter().next().map(|(image, _)| image)
+}
+
+/// Decode the frames of the background image like `load_image`, empty if it can't be used.
+pub fn load_frames(path: &Path) -> Vec<(RgbaImage, Duration)> {
+    match decode_frames(path) {
+        Ok(frames) => frames,
+        Err(err) => {
+            warn!("Could not load the background image {}: {}", path.display(), err);
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+
+    use super::{Effect, Gradient, GradientKind, Pattern, PatternKind, Stop};
+
+    #[test]
+    fn effects_are_tables_or_short_forms() {
+        #[derive(Deserialize)]
+        struct Background {
+            effects: Vec<Effect>,
+        }
+        let parse = |source| toml::from_str::<Background>(source).map(|bg| bg.effects);
+
+        assert_eq!(
+            parse("effects = [{ gaussian_blur = 8 }, 'pixelate:4', { darken = 0.25 }]").unwrap(),
+            vec![Effect::GaussianBlur(8), Effect::Pixelate(4), Effect::Darken(0.25)]
+        );
+        assert!(parse("effects = [{ blur = 2, darken = 0.5 }]").is_err());
+        assert!(parse("effects = [{ blur = 2.5 }]").is_err());
+        assert!(parse("effects = [{ darken = 2 }]").is_err());
+        assert!(parse("effects = [{ sharpen = 1 }]").is_err());
+
+        assert_eq!(
+            parse("effects = [{ tint = '#ff000080' }, 'contrast:1.5', { brightness = -0.1 }]")
+                .unwrap(),
+            vec![Effect::Tint(0x80ff0000), Effect::Contrast(1.5), Effect::Brightness(-0.1)]
+        );
+        assert!(parse("effects = [{ tint = 'color1' }]").is_err());
+
+        let value = toml::Value::try_from(Effect::Darken(0.3)).unwrap();
+        assert_eq!(value.to_string(), "darken = 0.3\n");
+        let value = toml::Value::try_from(Effect::Tint(0x80ff0000)).unwrap();
+        assert_eq!(value.to_string(), "tint = \"#ff000080\"\n");
+    }
+
+    #[test]
+    fn stops_without_positions_are_spread_evenly() {
+        let stop = |position| Stop { color: 0, position };
+        let gradient = |stops| Gradient { kind: GradientKind::Linear, angle: None, stops };
+        let positions = |stops| -> Vec<f64> {
+            gradient(stops).positions().into_iter().map(|(position, _)| position).collect()
+        };
+
+        assert_eq!(positions(vec![stop(None), stop(None), stop(None)]), vec![0.0, 0.5, 1.0]);
+        assert_eq!(
+            positions(vec![stop(None), stop(None), stop(Some(0.8)), stop(None), stop(None)]),
+            vec![0.0, 0.4, 0.8, 0.9, 1.0]
+        );
+        // Positions never go backwards
+        assert_eq!(positions(vec![stop(Some(0.6)), stop(Some(0.2))]), vec![0.6, 0.6]);
+        assert_eq!(positions(vec![]), Vec::<f64>::new());
+    }
+
+    #[test]
+    fn gradients_and_patterns_parse() {
+        assert_eq!(
+            "linear:45:#000,rgb(0, 0, 255) 25%,white".parse::<Gradient>().unwrap(),
+            Gradient {
+                kind: GradientKind::Linear,
+                angle: Some(45.0),
+                stops: vec![
+                    Stop { color: 0xff000000, position: None },
+                    Stop { color: 0xff0000ff, position: Some(0.25) },
+                    Stop { color: 0xffffffff, position: None },
+                ],
+            }
+        );
+        assert_eq!("radial:#000,#fff".parse::<Gradient>().unwrap().stops.len(), 2);
+        assert!("conic:#000,#fff".parse::<Gradient>().is_err());
+        assert_eq!(
+            "dots:24:#ffffff20".parse::<Pattern>().unwrap(),
+            Pattern { kind: PatternKind::Dots, size: 24, color: 0x20ffffff }
+        );
+
+        #[derive(Deserialize)]
+        struct Background {
+            gradient: Gradient,
+        }
+        let gradient = toml::from_str::<Background>(
+            "[gradient]\ntype = 'radial'\nstops = ['#1e1e2e', '#89b4fa 60%', 'black']",
+        )
+        .unwrap()
+        .gradient;
+        assert_eq!(gradient.kind, GradientKind::Radial);
+        assert_eq!(gradient.stops[1], Stop { color: 0xff89b4fa, position: Some(0.6) });
+        assert_eq!(gradient.stops[1].to_string(), "#89b4fa 60%");
+    }
+
+    #[test]
+    fn directories_list_their_images() {
+        let dir = std::env::temp_dir().join(format!("waylock-slideshow-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested.png")).unwrap();
+        for name in ["b.JPG", "a.png", "notes.txt", "c.jpeg"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        let images = super::list_images(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<_> = images.iter().map(|path| path.file_name().unwrap()).collect();
+        assert_eq!(names, ["a.png", "b.JPG", "c.jpeg"]);
+        assert!(super::list_images(&dir).is_err());
+    }
+
+    #[test]
+    fn animated_gifs_decode_every_frame() {
+        use image::codecs::gif::GifEncoder;
+        use image::{Delay, Frame, Rgba, RgbaImage};
+
+        let path = std::env::temp_dir().join(format!("waylock-{}.gif", std::process::id()));
+        let frame = |color, ms| {
+            let delay = Delay::from_numer_denom_ms(ms, 1);
+            Frame::from_parts(RgbaImage::from_pixel(2, 2, Rgba(color)), 0, 0, delay)
+        };
+        let mut gif = Vec::new();
+        GifEncoder::new(&mut gif)
+            .encode_frames(vec![frame([255, 0, 0, 255], 500), frame([0, 0, 255, 255], 0)])
+            .unwrap();
+        std::fs::write(&path, gif).unwrap();
+        let frames = super::decode_frames(&path).unwrap();
+        let first = super::decode(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let delays: Vec<_> = frames.iter().map(|(_, delay)| delay.as_millis()).collect();
+        assert_eq!(delays, vec![500, 100]);
+        assert_eq!(frames[1].0.get_pixel(1, 1), &Rgba([0, 0, 255, 255]));
+        assert_eq!(first, frames[0].0);
+    }
+
+    #[test]
+    fn large_animations_keep_their_first_frame() {
+        use image::codecs::gif::GifEncoder;
+        use image::{Delay, Frame, Rgba, RgbaImage};
+
+        let path = std::env::temp_dir().join(format!("waylock-large-{}.gif", std::process::id()));
+        let frame = |color| {
+            let delay = Delay::from_numer_denom_ms(100, 1);
+            Frame::from_parts(RgbaImage::from_pixel(2, 2, Rgba(color)), 0, 0, delay)
+        };
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        let mut gif = Vec::new();
+        GifEncoder::new(&mut gif).encode_frames(colors.iter().map(|c| frame(*c))).unwrap();
+        std::fs::write(&path, gif).unwrap();
+        // Each frame takes 2 * 2 * 4 bytes
+        let all = super::decode_frames_within(&path, 3 * 16).unwrap();
+        let capped = super::decode_frames_within(&path, 2 * 16).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(all.len(), 3);
+        assert_eq!(capped.len(), 1);
+        assert_eq!(capped[0].0.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+    }
+}
@@ -0,0 +1,381 @@
+use std::env;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::forward_to_deserialize_any;
+
+use crate::config::font::load_font;
+use crate::config::options::{default_config_path, Background, Colors, Fonts};
+use crate::config::{background, layer, palette, theme, ConfigError, Options};
+use crate::supervisor::exit_code;
+
+/// Commands `sh` runs without looking them up in `$PATH`.
+const SHELL_BUILTINS: &[&str] =
+    &["cd", "echo", "eval", "exec", "exit", "false", "kill", "printf", "read", "test", "true", "["];
+
+/// A problem found in a config file, located by line and column if possible.
+struct Diagnostic {
+    error: bool,
+    position: Option<(usize, usize)>,
+    message: String,
+}
+
+/// Check a config file without locking the screen and print every problem found. Returns
+/// `CONFIG_ERROR` if there are any errors, warnings alone don't fail the check.
+pub fn check_config(file: Option<PathBuf>) -> i32 {
+    let path = match file.map_or_else(default_config_path, Ok) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("{}", err);
+            return exit_code::CONFIG_ERROR;
+        }
+    };
+    let source = match std::fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("{}: error: {}", path.display(), ConfigError::IO(err));
+            return exit_code::CONFIG_ERROR;
+        }
+    };
+
+    let diagnostics = check(&source);
+    for diagnostic in &diagnostics {
+        let level = if diagnostic.error { "error" } else { "warning" };
+        match diagnostic.position {
+            Some((line, col)) => {
+                println!("{}:{}:{}: {}: {}", path.display(), line, col, level, diagnostic.message)
+            }
+            None => println!("{}: {}: {}", path.display(), level, diagnostic.message),
+        }
+    }
+
+    let errors = diagnostics.iter().filter(|diagnostic| diagnostic.error).count();
+    let warnings = diagnostics.len() - errors;
+    println!("{}: {} error(s), {} warning(s)", path.display(), errors, warnings);
+    if errors == 0 {
+        exit_code::UNLOCKED
+    } else {
+        exit_code::CONFIG_ERROR
+    }
+}
+
+fn check(source: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut unknown = Vec::new();
+    let mut deserializer = toml::Deserializer::new(source);
+    let parsed: Result<Options, _> =
+        serde_ignored::deserialize(&mut deserializer, |path| unknown.push(path.to_string()));
+
+    let options = match parsed {
+        Ok(options) => options,
+        Err(err) => {
+            report_unknown(source, "", unknown, &mut diagnostics);
+            let position = err.line_col().map(|(line, col)| (line + 1, col + 1));
+            diagnostics.push(Diagnostic { error: true, position, message: err.to_string() });
+            return diagnostics;
+        }
+    };
+    report_unknown(source, "", unknown, &mut diagnostics);
+
+    for (name, profile) in &options.profiles {
+        let prefix = format!("profile.{}.", name);
+        let mut unknown = Vec::new();
+        let parsed = layer::normalize(profile.clone(), &mut |key| unknown.push(key));
+        report_unknown(source, &prefix, unknown, &mut diagnostics);
+        if let Err(err) = parsed {
+            diagnostics.push(Diagnostic {
+                error: true,
+                position: locate(source, &prefix[..prefix.len() - 1]),
+                message: format!("invalid profile {}: {}", name, err),
+            });
+        }
+    }
+
+    let fonts = [
+        ("font", &options.font),
+        ("fonts.font_clock", &options.fonts.font_clock),
+        ("fonts.font_user", &options.fonts.font_user),
+        ("fonts.font_password", &options.fonts.font_password),
+        ("fonts.font_message", &options.fonts.font_message),
+        ("fonts.font_banner", &options.fonts.font_banner),
+    ];
+    for (key, chain) in fonts.iter() {
+        for font in chain.iter().flatten() {
+            if load_font(font).is_none() {
+                // The next font of the chain, at least the built-in one, is used instead
+                diagnostics.push(Diagnostic {
+                    error: false,
+                    position: locate(source, key),
+                    message: format!("font \"{}\" is not available", font),
+                });
+            }
+        }
+    }
+
+    if let Some(image) = &options.background.image {
+        // The lock still works, showing color_bg instead
+        let problem = if background::is_directory(image) {
+            match background::list_images(image) {
+                Ok(images) if images.is_empty() => {
+                    Some("contains no PNG or JPEG images".to_owned())
+                }
+                Ok(_) => None,
+                Err(err) => Some(format!("can't be read: {}", err)),
+            }
+            .map(|problem| format!("background directory {} {}", image.display(), problem))
+        } else {
+            background::decode(image)
+                .err()
+                .map(|err| format!("background image {} can't be used: {}", image.display(), err))
+        };
+        if let Some(message) = problem {
+            diagnostics.push(Diagnostic {
+                error: false,
+                position: locate(source, "background.image"),
+                message,
+            });
+        }
+    }
+
+    let mut colors = options.colors.clone();
+    if let Err(err) = palette::resolve(&mut colors, options.palette.as_deref()) {
+        // Without a palette in this file, another layer may still set one
+        diagnostics.push(Diagnostic {
+            error: options.palette.is_some(),
+            position: locate(source, if options.palette.is_some() { "palette" } else { "colors" }),
+            message: err.to_string(),
+        });
+    }
+
+    if let Some(theme) = &options.theme {
+        if let Err(err) = theme::load(theme, &mut |_, _| {}) {
+            diagnostics.push(Diagnostic {
+                error: true,
+                position: locate(source, "theme"),
+                message: err.to_string(),
+            });
+        }
+    }
+
+    for (key, command) in
+        [("fail_command", &options.fail_command), ("crash_command", &options.crash_command)]
+    {
+        let program = match command.as_deref().and_then(|command| command.split_whitespace().next())
+        {
+            Some(program) => program,
+            None => continue,
+        };
+        if !command_exists(program) {
+            diagnostics.push(Diagnostic {
+                error: true,
+                position: locate(source, key),
+                message: format!("`{}` runs `{}`, which could not be found", key, program),
+            });
+        }
+    }
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.position);
+    diagnostics
+}
+
+/// Warn about unknown keys, which are ignored. `prefix` is the table they are in.
+fn report_unknown(
+    source: &str,
+    prefix: &str,
+    unknown: Vec<String>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    for key in unknown {
+        let mut message = format!("unknown key `{}{}` is ignored", prefix, key);
+        if let Some(suggestion) = suggest(&key) {
+            message.push_str(&format!(", did you mean `{}{}`?", prefix, suggestion));
+        }
+        let position = locate(source, &format!("{}{}", prefix, key));
+        diagnostics.push(Diagnostic { error: false, position, message });
+    }
+}
+
+fn command_exists(program: &str) -> bool {
+    let executable = |path: &Path| {
+        path.metadata().map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    };
+
+    if program.contains('/') {
+        return executable(Path::new(program)).unwrap_or(false);
+    }
+    SHELL_BUILTINS.contains(&program)
+        || env::var_os("PATH").is_some_and(|paths| {
+            env::split_paths(&paths).any(|dir| executable(&dir.join(program)).unwrap_or(false))
+        })
+}
+
+/// Find the line and column of a dotted key, assuming it is written in its table.
+fn locate(source: &str, key: &str) -> Option<(usize, usize)> {
+    let (table, name) = match key.rfind('.') {
+        Some(dot) => (&key[..dot], &key[dot + 1..]),
+        None => ("", key),
+    };
+
+    let mut current = "";
+    for (line, text) in source.lines().enumerate() {
+        let trimmed = text.trim_start();
+        if let Some(header) = trimmed.strip_prefix('[') {
+            current = header.split(']').next().unwrap_or("").trim();
+            if current == key {
+                return Some((line + 1, text.len() - trimmed.len() + 1));
+            }
+        } else if current == table {
+            let found =
+                trimmed.strip_prefix(name).is_some_and(|rest| rest.trim_start().starts_with('='));
+            if found {
+                return Some((line + 1, text.len() - trimmed.len() + 1));
+            }
+        }
+    }
+    None
+}
+
+/// Suggest a known key for a misspelled one, within the same table.
+fn suggest(key: &str) -> Option<String> {
+    let (prefix, name, fields) = match key.rfind('.') {
+        Some(dot) if &key[..dot] == "colors" => ("colors.", &key[dot + 1..], fields::<Colors>()),
+        Some(dot) if &key[..dot] == "fonts" => ("fonts.", &key[dot + 1..], fields::<Fonts>()),
+        Some(dot) if &key[..dot] == "background" => {
+            ("background.", &key[dot + 1..], fields::<Background>())
+        }
+        Some(_) => return None,
+        None => ("", key, fields::<Options>()),
+    };
+
+    fields
+        .iter()
+        .map(|field| (distance(name, field), field))
+        .filter(|(distance, _)| *distance <= 2.max(name.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, field)| format!("{}{}", prefix, field))
+}
+
+/// Levenshtein distance between two keys.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + (ca != *cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The keys a struct accepts, taken from its `Deserialize` implementation.
+fn fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+/// A deserializer that only records the field names of the struct asking for them.
+struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+impl<'de, 'a> Deserializer<'de> for FieldNames<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(de::Error::custom("only the fields are needed"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option
+        unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn messages(source: &str) -> Vec<String> {
+        super::check(source)
+            .into_iter()
+            .map(|diagnostic| match diagnostic.position {
+                Some((line, col)) => format!("{}:{} {}", line, col, diagnostic.message),
+                None => diagnostic.message,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn misspelled_keys_get_suggestions() {
+        assert_eq!(
+            messages("fail_comand = 'true'\n\n[colors]\n  colr_init = '#ffffff'\n"),
+            vec![
+                "1:1 unknown key `fail_comand` is ignored, did you mean `fail_command`?",
+                "4:3 unknown key `colors.colr_init` is ignored, did you mean `colors.color_init`?",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_background_images_are_warnings() {
+        assert_eq!(
+            messages(
+                "[background]
+image = '/nonexistent/wall.png'
+mod = 'fit'
+"
+            ),
+            vec![
+                "2:1 background image /nonexistent/wall.png can't be used: No such file or \
+                 directory (os error 2)",
+                "3:1 unknown key `background.mod` is ignored, did you mean `background.mode`?",
+            ]
+        );
+    }
+
+    #[test]
+    fn profiles_are_checked() {
+        assert_eq!(
+            messages("[profile.office]\nmesage = 'Desk 4.12'\n"),
+            vec!["2:1 unknown key `profile.office.mesage` is ignored, did you mean `profile.office.message`?"]
+        );
+    }
+
+    #[test]
+    fn invalid_colors_are_located() {
+        let diagnostics = super::check("[colors]\ncolor_fail = '#12345'\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].error);
+        assert_eq!(diagnostics[0].position, Some((2, 14)));
+    }
+
+    #[test]
+    fn missing_commands_are_errors() {
+        assert_eq!(
+            messages(
+                "fail_command = 'echo failed'\ncrash_command = 'waylock-no-such-command -v'\n"
+            ),
+            vec!["2:1 `crash_command` runs `waylock-no-such-command`, which could not be found"]
+        );
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(super::distance("color_init", "color_init"), 0);
+        assert_eq!(super::distance("colr_init", "color_init"), 1);
+        assert_eq!(super::distance("init_colour", "init_color"), 1);
+        assert_eq!(super::distance("", "font"), 4);
+    }
+}
@@ -1,18 +1,26 @@
 use std::{error, fmt, num, str};
 use crate::config::ConfigError;
+use serde::{de, Deserializer, Serializer};
 
 #[derive(Debug)]
 pub enum Error {
     InvalidLength,
     InvalidPrefix,
+    InvalidFunction(&'static str),
+    OutOfRange,
     ParseInt(num::ParseIntError),
+    ParseFloat(num::ParseFloatError),
 }
 
 impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self {
-            Self::InvalidLength | Self::InvalidPrefix => None,
+            Self::InvalidLength
+            | Self::InvalidPrefix
+            | Self::InvalidFunction(_)
+            | Self::OutOfRange => None,
             Self::ParseInt(err) => err.source(),
+            Self::ParseFloat(err) => err.source(),
         }
     }
 }
@@ -20,29 +28,387 @@ impl error::Error for Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::InvalidLength => write!(f, "invalid length, color must have exactly 6 digits"),
-            Self::InvalidPrefix => write!(f, "invalid color prefix, must start with '#' or '0x'"),
+            Self::InvalidLength => write!(f, "invalid length, color must have 3, 4, 6 or 8 digits"),
+            Self::InvalidPrefix => write!(
+                f,
+                "unknown color, must be a CSS color name, start with '#' or '0x', \
+                 or use rgb(), rgba(), hsl() or hsla()"
+            ),
+            Self::InvalidFunction(expected) => {
+                write!(f, "invalid color function, expected {}", expected)
+            }
+            Self::OutOfRange => write!(f, "color component out of range"),
             Self::ParseInt(err) => write!(f, "parse error: {}", err),
+            Self::ParseFloat(err) => write!(f, "parse error: {}", err),
         }
     }
 }
 
+/// Parse a color into 0xAARRGGBB, the pixel format of the lock surface. Accepted are
+/// `#RGB`, `#RGBA`, `#RRGGBB` and `#RRGGBBAA` (also with a `0x` prefix), the functions `rgb()`,
+/// `rgba()`, `hsl()` and `hsla()` as well as CSS color names. Colors without alpha are opaque.
 pub fn from_str(s: &str) -> Result<u32, ConfigError> {
-    let digits = None
-        .or_else(|| s.strip_prefix("0x"))
-        .or_else(|| s.strip_prefix('#'))
-        .map_or(Err(ConfigError::Color(Error::InvalidPrefix)), Ok)?;
+    let s = s.trim();
+    let color = match s.strip_prefix("0x").or_else(|| s.strip_prefix('#')) {
+        Some(digits) => from_hex(digits),
+        None => match s.strip_suffix(')').and_then(|s| s.split_once('(')) {
+            Some((function, args)) => from_function(function.trim(), args),
+            None => from_name(s),
+        },
+    };
+    color.map_err(ConfigError::Color)
+}
+
+fn from_hex(digits: &str) -> Result<u32, Error> {
+    let value = match digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+        // from_str_radix takes a leading + for a sign, but fails on it alone
+        Some(c) => u32::from_str_radix(&c.to_string(), 16),
+        None => u32::from_str_radix(digits, 16),
+    }
+    .map_err(Error::ParseInt);
+    match digits.len() {
+        // Short forms repeat every digit: #abc is #aabbcc
+        3 | 4 => {
+            let value = value?;
+            let nibbles = digits.len() as u32;
+            let nibble = |i: u32| (value >> (4 * (nibbles - 1 - i)) & 0xf) * 0x11;
+            let alpha = if nibbles == 4 { nibble(3) } else { 0xff };
+            Ok(argb(nibble(0), nibble(1), nibble(2), alpha))
+        }
+        6 => Ok(value? | 0xff000000),
+        8 => value.map(|value| value >> 8 | (value & 0xff) << 24),
+        _ => Err(Error::InvalidLength),
+    }
+}
+
+fn from_function(function: &str, args: &str) -> Result<u32, Error> {
+    // Both the legacy `rgb(1, 2, 3)` and the modern `rgb(1 2 3 / 50%)` syntax are accepted
+    let args: Vec<&str> = if args.contains(',') {
+        args.split(',').map(str::trim).collect()
+    } else {
+        args.split_whitespace().filter(|arg| *arg != "/").collect()
+    };
+
+    let alpha = match args.get(3) {
+        Some(alpha) => (component(alpha, 1.0)? * 255.0).round() as u32,
+        None => 0xff,
+    };
+    match (function.to_ascii_lowercase().as_str(), args.len()) {
+        ("rgb" | "rgba", 3 | 4) => {
+            let channel = |arg| component(arg, 255.0).map(|value| value.round() as u32);
+            Ok(argb(channel(args[0])?, channel(args[1])?, channel(args[2])?, alpha))
+        }
+        ("hsl" | "hsla", 3 | 4) => {
+            let hue = args[0].strip_suffix("deg").unwrap_or(args[0]);
+            let hue = hue.parse::<f64>().map_err(Error::ParseFloat)?.rem_euclid(360.0);
+            let percentage = |arg: &str| component(arg.strip_suffix('%').unwrap_or(arg), 100.0);
+            let saturation = percentage(args[1])? / 100.0;
+            let lightness = percentage(args[2])? / 100.0;
+            let (red, green, blue) = hsl_to_rgb(hue, saturation, lightness);
+            Ok(argb(red, green, blue, alpha))
+        }
+        ("rgb" | "rgba" | "hsl" | "hsla", _) => Err(Error::InvalidFunction("3 or 4 arguments")),
+        _ => Err(Error::InvalidFunction("rgb(), rgba(), hsl() or hsla()")),
+    }
+}
 
-    if digits.len() != 6 {
-        return Err(ConfigError::Color(Error::InvalidLength));
+fn from_name(name: &str) -> Result<u32, Error> {
+    let name = name.to_ascii_lowercase();
+    if name == "transparent" {
+        return Ok(0);
     }
+    NAMED_COLORS
+        .binary_search_by_key(&name.as_str(), |(name, _)| name)
+        .map(|index| NAMED_COLORS[index].1 | 0xff000000)
+        .map_err(|_| Error::InvalidPrefix)
+}
 
-    match u32::from_str_radix(digits, 16) {
-        Ok(number) => Ok(number),
-        Err(err) => Err(ConfigError::Color(Error::ParseInt(err))),
+/// Parse a number between 0 and `max`, or a percentage of `max`.
+fn component(arg: &str, max: f64) -> Result<f64, Error> {
+    let value = match arg.strip_suffix('%') {
+        Some(percentage) => percentage.parse::<f64>().map_err(Error::ParseFloat)? / 100.0 * max,
+        None => arg.parse::<f64>().map_err(Error::ParseFloat)?,
+    };
+    if (0.0..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err(Error::OutOfRange)
     }
 }
 
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> (u32, u32, u32) {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let (red, green, blue) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let channel = |value: f64| ((value + m) * 255.0).round() as u32;
+    (channel(red), channel(green), channel(blue))
+}
+
+fn argb(red: u32, green: u32, blue: u32, alpha: u32) -> u32 {
+    alpha << 24 | red << 16 | green << 8 | blue
+}
+
+/// A color as given in the config. It is either a color, or the name of a slot of the configured
+/// palette, like `color1` or `base08`, which is resolved before the `Config` is built.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColorSpec {
+    Color(u32),
+    Slot(String),
+}
+
+impl ColorSpec {
+    /// The color, if it isn't an unresolved palette slot.
+    pub fn color(&self) -> Option<u32> {
+        match self {
+            Self::Color(color) => Some(*color),
+            Self::Slot(_) => None,
+        }
+    }
+}
+
+/// Parse a color like [`from_str`], taking any other name as a palette slot.
+pub fn parse_spec(s: &str) -> Result<ColorSpec, ConfigError> {
+    match from_str(s) {
+        Ok(color) => Ok(ColorSpec::Color(color)),
+        Err(ConfigError::Color(Error::InvalidPrefix)) if is_slot(s.trim()) => {
+            Ok(ColorSpec::Slot(s.trim().to_ascii_lowercase()))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn is_slot(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic())
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Deserialize a color given either as a string parsed by [`parse_spec`] or as an opaque
+/// 0xRRGGBB integer.
+pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<ColorSpec>, D::Error> {
+    struct ColorVisitor;
+
+    impl<'de> de::Visitor<'de> for ColorVisitor {
+        type Value = Option<ColorSpec>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a color like \"#rrggbb\" or 0xrrggbb, or a palette color")
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            if v > 0xffffff {
+                return Err(E::invalid_value(de::Unexpected::Unsigned(v), &self));
+            }
+            Ok(Some(ColorSpec::Color(v as u32 | 0xff000000)))
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            if v < 0 {
+                return Err(E::invalid_value(de::Unexpected::Signed(v), &self));
+            }
+            self.visit_u64(v as u64)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            parse_spec(v).map(Some).map_err(|err| match err {
+                ConfigError::Color(err) => E::custom(format!("invalid color \"{}\": {}", v, err)),
+                err => E::custom(err),
+            })
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<Self::Value, D::Error> {
+            d.deserialize_any(self)
+        }
+    }
+
+    deserializer.deserialize_option(ColorVisitor)
+}
+
+/// Serialize a color as a "#rrggbb" string, or "#rrggbbaa" if it isn't opaque, and a palette
+/// slot by its name. [`deserialize`] reads all of them back.
+pub fn serialize<S: Serializer>(
+    color: &Option<ColorSpec>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match color {
+        Some(ColorSpec::Color(color)) => serializer.serialize_str(&to_string(*color)),
+        Some(ColorSpec::Slot(slot)) => serializer.serialize_str(slot),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn to_string(color: u32) -> String {
+    match color >> 24 {
+        0xff => format!("#{:06x}", color & 0xffffff),
+        alpha => format!("#{:06x}{:02x}", color & 0xffffff, alpha),
+    }
+}
+
+/// CSS named colors, sorted by name for binary search. Opaque, in 0xRRGGBB.
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
 #[cfg(test)]
 mod tests {
     use super::Error;
@@ -51,6 +417,7 @@ mod tests {
     macro_rules! test {
         ($name: ident: $str: expr, $result: pat) => {
             #[test]
+            #[allow(clippy::redundant_pattern_matching)]
             fn $name() {
                 assert!(matches!(super::from_str($str), $result));
             }
@@ -61,13 +428,68 @@ mod tests {
     test!(binary_prefix_6_digit: "0b01abEF", Err(ConfigError::Color(Error::InvalidPrefix)));
     test!(alphabetic_prefix_6_digit: "a01abEF", Err(ConfigError::Color(Error::InvalidPrefix)));
 
-    test!(octothorpe_6_digit: "#01abEF", Ok(_));
+    test!(octothorpe_6_digit: "#01abEF", Ok(0xff01abef));
     test!(octothorpe_short: "#01234", Err(ConfigError::Color(Error::InvalidLength)));
-    test!(octothorpe_long: "#01234567", Err(ConfigError::Color(Error::InvalidLength)));
+    test!(octothorpe_long: "#012345678", Err(ConfigError::Color(Error::InvalidLength)));
     test!(octothorpe_invalid_digit: "#012z45", Err(ConfigError::Color(Error::ParseInt(_))));
 
-    test!(hex_6_digit: "#01abEF", Ok(_));
-    test!(hex_short: "#01234", Err(ConfigError::Color(Error::InvalidLength)));
-    test!(hex_long: "#01234567", Err(ConfigError::Color(Error::InvalidLength)));
-    test!(hex_invalid_digit: "#012z45", Err(ConfigError::Color(Error::ParseInt(_))));
+    test!(hex_6_digit: "0x01abEF", Ok(0xff01abef));
+    test!(hex_short: "0x01234", Err(ConfigError::Color(Error::InvalidLength)));
+    test!(hex_long: "0x012345678", Err(ConfigError::Color(Error::InvalidLength)));
+    test!(hex_invalid_digit: "0x012z45", Err(ConfigError::Color(Error::ParseInt(_))));
+
+    test!(octothorpe_3_digit: "#1aF", Ok(0xff11aaff));
+    test!(octothorpe_4_digit: "#1aF8", Ok(0x8811aaff));
+    test!(octothorpe_8_digit: "#01abEF80", Ok(0x8001abef));
+    test!(hex_8_digit: "0x01abEF00", Ok(0x0001abef));
+    test!(octothorpe_3_invalid_digit: "#1zF", Err(ConfigError::Color(Error::ParseInt(_))));
+    test!(octothorpe_plus_sign: "#+12", Err(ConfigError::Color(Error::ParseInt(_))));
+    test!(hex_plus_sign: "0x+1abEF", Err(ConfigError::Color(Error::ParseInt(_))));
+
+    test!(rgb: "rgb(1, 171, 239)", Ok(0xff01abef));
+    test!(rgb_percent: "rgb(100%, 50%, 0%)", Ok(0xffff8000));
+    test!(rgb_space_separated: "rgb(1 171 239 / 50%)", Ok(0x8001abef));
+    test!(rgb_with_alpha: "rgb(1, 171, 239, 0.5)", Ok(0x8001abef));
+    test!(rgba: "rgba(1, 171, 239, 0.25)", Ok(0x4001abef));
+    test!(rgba_uppercase: "RGBA(0, 0, 0, 0)", Ok(0x00000000));
+    test!(rgb_missing_argument: "rgb(1, 171)", Err(ConfigError::Color(Error::InvalidFunction(_))));
+    test!(rgb_out_of_range: "rgb(256, 0, 0)", Err(ConfigError::Color(Error::OutOfRange)));
+    test!(rgba_alpha_out_of_range: "rgba(0, 0, 0, 2)", Err(ConfigError::Color(Error::OutOfRange)));
+    test!(rgb_invalid_number: "rgb(a, 0, 0)", Err(ConfigError::Color(Error::ParseFloat(_))));
+    test!(unknown_function: "cmyk(0, 0, 0, 0)", Err(ConfigError::Color(Error::InvalidFunction(_))));
+
+    test!(hsl_red: "hsl(0, 100%, 50%)", Ok(0xffff0000));
+    test!(hsl_green: "hsl(120deg, 100%, 25%)", Ok(0xff008000));
+    test!(hsl_wraps_hue: "hsl(-120, 100%, 50%)", Ok(0xff0000ff));
+    test!(hsl_gray: "hsl(200, 0%, 50%)", Ok(0xff808080));
+    test!(hsla: "hsla(240, 100%, 50%, 50%)", Ok(0x800000ff));
+    test!(hsl_out_of_range: "hsl(0, 120%, 50%)", Err(ConfigError::Color(Error::OutOfRange)));
+
+    test!(named: "rebeccapurple", Ok(0xff663399));
+    test!(named_mixed_case: "DarkSlateGray", Ok(0xff2f4f4f));
+    test!(named_transparent: "transparent", Ok(0x00000000));
+    test!(named_unknown: "notacolor", Err(ConfigError::Color(Error::InvalidPrefix)));
+
+    #[test]
+    fn palette_slots() {
+        use super::{parse_spec, ColorSpec};
+
+        assert_eq!(parse_spec("color1").ok(), Some(ColorSpec::Slot("color1".to_owned())));
+        assert_eq!(parse_spec("base0A").ok(), Some(ColorSpec::Slot("base0a".to_owned())));
+        assert_eq!(parse_spec("red").ok(), Some(ColorSpec::Color(0xffff0000)));
+        assert!(parse_spec("#12").is_err());
+        assert!(parse_spec("not a slot").is_err());
+    }
+
+    #[test]
+    fn named_colors_are_sorted() {
+        assert!(super::NAMED_COLORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn to_string_round_trips() {
+        for color in &[0xff01abef, 0x8001abef, 0x00000000] {
+            assert_eq!(super::from_str(&super::to_string(*color)).ok(), Some(*color));
+        }
+    }
 }
\ No newline at end of file
@@ -1,44 +1,395 @@
-use font_loader::system_fonts::{FontPropertyBuilder, get};
-use std::fmt::Display;
-use std::ops::Deref;
-use fontdue::Font;
+use font_loader::system_fonts::{get, FontPropertyBuilder};
+use fontdue::{Font, FontSettings};
 use log::error;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::path::PathBuf;
 
-pub fn load_font<T: Deref<Target=str> + Display >(font: T) -> Option<Font> {
-    let properties = FontPropertyBuilder::new()
-        .family(&font)
-        .build();
+/// Name of the font embedded in the binary, always the last font of a chain.
+pub const EMBEDDED_NAME: &str = "DejaVu Sans Mono (built-in)";
+const EMBEDDED: &[u8] = include_bytes!("fonts/DejaVuSansMono.ttf");
 
-    match get(&properties) {
-        None => {
-            eprintln!("Chosen font {} not found.", font);
-            error!("Font \"{}\" not available.", font);
-            None
-        },
-        Some((data, _c_int)) => match Font::from_bytes(data, Default::default()) {
-            Ok(font) => Some(font),
+/// OpenType weights and the fontconfig weights they map to, see `FcWeightFromOpenType`.
+const WEIGHTS: &[(u16, u16)] = &[
+    (100, 0),
+    (200, 40),
+    (300, 50),
+    (350, 55),
+    (380, 75),
+    (400, 80),
+    (500, 100),
+    (600, 180),
+    (700, 200),
+    (800, 205),
+    (900, 210),
+    (1000, 215),
+];
+
+/// Named weights, as in CSS.
+const WEIGHT_NAMES: &[(&str, u16)] = &[
+    ("thin", 100),
+    ("extralight", 200),
+    ("light", 300),
+    ("normal", 400),
+    ("regular", 400),
+    ("medium", 500),
+    ("semibold", 600),
+    ("bold", 700),
+    ("extrabold", 800),
+    ("black", 900),
+];
+
+/// A font, either a family looked up through fontconfig or a font file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FontSpec {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// OpenType weight, 400 is regular and 700 bold. Only used to look up a family.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_weight",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub weight: Option<u16>,
+    /// Only used to look up a family.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<Style>,
+    /// Index of the face in a font collection, like a `.ttc` file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Style {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+impl From<&str> for FontSpec {
+    /// A path if `font` contains a slash, a family otherwise.
+    fn from(font: &str) -> Self {
+        if font.contains('/') {
+            FontSpec { path: Some(PathBuf::from(font)), ..FontSpec::default() }
+        } else {
+            FontSpec { family: Some(font.to_owned()), ..FontSpec::default() }
+        }
+    }
+}
+
+impl FontSpec {
+    /// The string this font can be written as, if it has no other properties.
+    fn as_plain(&self) -> Option<String> {
+        if self.weight.is_some() || self.style.is_some() || self.index.is_some() {
+            return None;
+        }
+        match (&self.family, &self.path) {
+            (Some(family), None) if !family.contains('/') => Some(family.clone()),
+            (None, Some(path)) if path.to_string_lossy().contains('/') => {
+                Some(path.to_string_lossy().into_owned())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for FontSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.path, &self.family) {
+            (Some(path), _) => write!(f, "{}", path.display())?,
+            (None, Some(family)) => f.write_str(family)?,
+            (None, None) => f.write_str("<no family or path>")?,
+        }
+        if let Some(weight) = self.weight {
+            write!(f, " weight {}", weight)?;
+        }
+        match self.style {
+            Some(Style::Italic) => f.write_str(" italic")?,
+            Some(Style::Oblique) => f.write_str(" oblique")?,
+            _ => {}
+        }
+        if let Some(index) = self.index {
+            write!(f, " #{}", index)?;
+        }
+        Ok(())
+    }
+}
+
+/// Load a font from its file, or look up its family.
+pub fn load_font(font: &FontSpec) -> Option<Font> {
+    let (data, index) = match (&font.path, &font.family) {
+        (Some(path), _) => match std::fs::read(path) {
+            Ok(data) => (data, 0),
             Err(err) => {
-                error!("Could not load Font {}. ERROR: {}", font, err);
-                None
+                eprintln!("Chosen font {} could not be read: {}", font, err);
+                error!("Font file \"{}\" not readable: {}", path.display(), err);
+                return None;
             }
+        },
+        (None, Some(family)) => {
+            let mut properties = FontPropertyBuilder::new().family(&pattern(family, font.weight));
+            match font.style {
+                Some(Style::Italic) => properties = properties.italic(),
+                Some(Style::Oblique) => properties = properties.oblique(),
+                _ => {}
+            }
+
+            match get(&properties.build()) {
+                Some((data, index)) => (data, index as u32),
+                None => {
+                    eprintln!("Chosen font {} not found.", font);
+                    error!("Font \"{}\" not available.", font);
+                    return None;
+                }
+            }
+        }
+        (None, None) => {
+            eprintln!("Chosen font has neither a family nor a path.");
+            error!("Font without family or path.");
+            return None;
         }
+    };
+
+    let settings =
+        FontSettings { collection_index: font.index.unwrap_or(index), ..FontSettings::default() };
+    match Font::from_bytes(data, settings) {
+        Ok(loaded) => Some(loaded),
+        Err(err) => {
+            error!("Could not load Font {}. ERROR: {}", font, err);
+            None
+        }
+    }
+}
+
+/// The fontconfig pattern for a family with an optional weight. Characters with a meaning in
+/// patterns are escaped, so names like `Noto Sans Mono-CJK` are matched literally.
+fn pattern(family: &str, weight: Option<u16>) -> String {
+    let mut pattern = String::with_capacity(family.len());
+    for c in family.chars() {
+        if matches!(c, '\\' | '-' | ':' | ',') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    if let Some(weight) = weight {
+        // Values in the pattern are preferred over the regular weight font_loader adds
+        pattern.push_str(&format!(":weight={}", fontconfig_weight(weight)));
     }
+    pattern
+}
+
+/// Interpolate between the known OpenType to fontconfig weights.
+fn fontconfig_weight(weight: u16) -> u16 {
+    let weight = weight.max(WEIGHTS[0].0).min(WEIGHTS[WEIGHTS.len() - 1].0);
+    WEIGHTS
+        .windows(2)
+        .find(|pair| weight <= pair[1].0)
+        .map(|pair| {
+            let ((ot0, fc0), (ot1, fc1)) = (pair[0], pair[1]);
+            fc0 + (weight - ot0) * (fc1 - fc0) / (ot1 - ot0)
+        })
+        .unwrap_or(WEIGHTS[0].1)
+}
+
+pub fn embedded_font() -> Font {
+    Font::from_bytes(EMBEDDED, Default::default()).expect("The embedded font is valid")
+}
+
+/// All fonts used by the UI. Elements refer to them by their index in chains of fallbacks, so
+/// a font shared by several chains is only loaded once.
+pub struct FontSet {
+    pub fonts: Vec<Font>,
+    pub names: Vec<String>,
+    /// Indices of the fonts loaded so far, `None` for fonts that are not available.
+    loaded: HashMap<String, Option<usize>>,
+}
+
+impl FontSet {
+    /// A set of just the embedded font, at index 0.
+    pub fn new() -> Self {
+        FontSet {
+            fonts: vec![embedded_font()],
+            names: vec![EMBEDDED_NAME.to_owned()],
+            loaded: HashMap::new(),
+        }
+    }
+
+    /// Load the available fonts of `specs` and return their indices, in order, followed by the
+    /// embedded font.
+    pub fn chain(&mut self, specs: &[FontSpec]) -> Vec<usize> {
+        let mut chain: Vec<usize> = specs
+            .iter()
+            .filter_map(|spec| {
+                let name = spec.to_string();
+                if let Some(index) = self.loaded.get(&name) {
+                    return *index;
+                }
+                let index = load_font(spec).map(|font| {
+                    self.fonts.push(font);
+                    self.names.push(name.clone());
+                    self.fonts.len() - 1
+                });
+                self.loaded.insert(name, index);
+                index
+            })
+            .collect();
+        chain.push(0);
+        chain
+    }
+}
+
+/// Accept a single font as well as a list of fonts.
+pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<FontSpec>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Entry {
+        Name(String),
+        Table(FontSpec),
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Fonts {
+        One(Entry),
+        Chain(Vec<Entry>),
+    }
+
+    let entry = |entry| match entry {
+        Entry::Name(name) => FontSpec::from(name.as_str()),
+        Entry::Table(spec) => spec,
+    };
+    Ok(Option::<Fonts>::deserialize(deserializer)?.map(|fonts| match fonts {
+        Fonts::One(one) => vec![entry(one)],
+        Fonts::Chain(chain) => chain.into_iter().map(entry).collect(),
+    }))
+}
+
+/// Write a chain as a list of strings if possible, as TOML can't mix them with tables.
+pub fn serialize<S: Serializer>(
+    fonts: &Option<Vec<FontSpec>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match fonts {
+        Some(fonts) => match fonts.iter().map(FontSpec::as_plain).collect::<Option<Vec<_>>>() {
+            Some(plain) => plain.serialize(serializer),
+            None => fonts.serialize(serializer),
+        },
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_weight<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u16>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Weight {
+        Number(u16),
+        Name(String),
+    }
+
+    let weight = match Weight::deserialize(deserializer)? {
+        Weight::Number(weight) => weight,
+        Weight::Name(name) => WEIGHT_NAMES
+            .iter()
+            .find(|(known, _)| name.eq_ignore_ascii_case(known))
+            .map(|(_, weight)| *weight)
+            .ok_or_else(|| de::Error::custom(format!("unknown font weight \"{}\"", name)))?,
+    };
+    if !(1..=1000).contains(&weight) {
+        return Err(de::Error::custom(format!("font weight {} is not within 1 to 1000", weight)));
+    }
+    Ok(Some(weight))
 }
 
 #[cfg(test)]
 mod test {
-    use font_loader::system_fonts::{FontPropertyBuilder, get};
     use fontdue::Font;
+    use serde::{Deserialize, Serialize};
 
+    use super::{FontSpec, Style};
+
+    #[test]
     fn default_font_should_exist() {
-        let properties = FontPropertyBuilder::new()
-            .family("monospace")
-            .build();
+        let _font = Font::from_bytes(super::EMBEDDED, Default::default())
+            .expect("Font should not be corrupted");
+    }
 
-        let (data, _c_int) = get(&properties)
-            .expect("Font should exist");
+    #[test]
+    fn chain_ends_with_the_embedded_font() {
+        let mut set = super::FontSet::new();
+        let missing = FontSpec::from("no such font/at all.ttf");
+        assert_eq!(set.chain(&[missing.clone(), missing]), vec![0]);
+        assert_eq!(set.names, vec![super::EMBEDDED_NAME]);
+        assert_ne!(set.fonts[0].lookup_glyph_index('a'), 0);
+    }
 
-        let _font = Font::from_bytes(data, Default::default())
-            .expect("Font should not be corrupted");
+    #[test]
+    fn specs_are_strings_or_tables() {
+        #[derive(Deserialize)]
+        struct Options {
+            #[serde(default, deserialize_with = "super::deserialize")]
+            font: Option<Vec<FontSpec>>,
+        }
+        let parse = |source| toml::from_str::<Options>(source).map(|options| options.font);
+
+        assert_eq!(parse("font = 'Hack'").unwrap(), Some(vec![FontSpec::from("Hack")]));
+        assert_eq!(
+            parse("font = ['./a.ttc', { family = 'Hack', weight = 'bold', style = 'italic' }]")
+                .unwrap(),
+            Some(vec![
+                FontSpec { path: Some("./a.ttc".into()), ..FontSpec::default() },
+                FontSpec {
+                    family: Some("Hack".to_owned()),
+                    weight: Some(700),
+                    style: Some(Style::Italic),
+                    ..FontSpec::default()
+                },
+            ])
+        );
+        assert_eq!(
+            parse("[font]\npath = '/fonts/noto.ttc'\nindex = 2").unwrap().unwrap()[0].index,
+            Some(2)
+        );
+        assert!(parse("font = { family = 'Hack', weight = 'heavy' }").is_err());
+        assert!(parse("font = { family = 'Hack', size = 12 }").is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn chains_serialize_like_they_are_written() {
+        #[derive(Serialize)]
+        struct Options {
+            #[serde(serialize_with = "super::serialize")]
+            font: Option<Vec<FontSpec>>,
+        }
+        let serialize = |fonts: Vec<FontSpec>| {
+            let options = toml::Value::try_from(Options { font: Some(fonts) }).unwrap();
+            toml::to_string(&options).unwrap()
+        };
+
+        let bold =
+            FontSpec { family: Some("Hack".to_owned()), weight: Some(700), ..FontSpec::default() };
+        assert_eq!(
+            serialize(vec![FontSpec::from("Hack"), FontSpec::from("/a.ttf")]),
+            "font = [\"Hack\", \"/a.ttf\"]\n"
+        );
+        assert_eq!(
+            serialize(vec![bold, FontSpec::from("Noto")]),
+            "[[font]]\nfamily = \"Hack\"\nweight = 700\n\n[[font]]\nfamily = \"Noto\"\n"
+        );
+    }
+
+    #[test]
+    fn weights_map_to_fontconfig() {
+        assert_eq!(super::fontconfig_weight(400), 80);
+        assert_eq!(super::fontconfig_weight(700), 200);
+        assert_eq!(super::fontconfig_weight(650), 190);
+        assert_eq!(super::fontconfig_weight(1), 0);
+        assert_eq!(super::pattern("Noto-Mono:x", Some(700)), "Noto\\-Mono\\:x:weight=200");
+    }
+}
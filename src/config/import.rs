@@ -0,0 +1,200 @@
+use std::env;
+use std::path::PathBuf;
+
+use crate::config::color::{self, ColorSpec};
+use crate::config::font::FontSpec;
+use crate::config::{IndicatorStyle, Options};
+use crate::supervisor::exit_code;
+
+/// Where swaylock looks for its config, in order.
+fn swaylock_config_paths() -> Vec<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+    let config_home =
+        env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).unwrap_or_else(|| home.join(".config"));
+    vec![
+        home.join(".swaylock/config"),
+        config_home.join("swaylock/config"),
+        PathBuf::from("/etc/swaylock/config"),
+    ]
+}
+
+/// Print a waylock.toml equivalent to a swaylock config. Options that can't be mapped are listed
+/// as comments at the top.
+pub fn import_swaylock(file: Option<PathBuf>) -> i32 {
+    let path = match file.or_else(|| swaylock_config_paths().into_iter().find(|path| path.exists()))
+    {
+        Some(path) => path,
+        None => {
+            eprintln!("No swaylock config found, pass its path.");
+            return exit_code::CONFIG_ERROR;
+        }
+    };
+    let source = match std::fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("Could not read {}: {}", path.display(), err);
+            return exit_code::CONFIG_ERROR;
+        }
+    };
+
+    let (options, unmapped) = convert(&source);
+    let config = match options.to_toml() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Could not serialize the configuration: {}", err);
+            return exit_code::CRASH;
+        }
+    };
+
+    println!("# Imported from {}", path.display());
+    if !unmapped.is_empty() {
+        println!("#\n# These swaylock options have no waylock equivalent and were left out:");
+        for option in &unmapped {
+            println!("#   {}", option);
+        }
+        eprintln!("{} swaylock option(s) could not be mapped.", unmapped.len());
+    }
+    println!();
+    print!("{}", config);
+    exit_code::UNLOCKED
+}
+
+/// Map the options of a swaylock config, one long option per line, to waylock `Options`.
+/// Returns the options together with the lines that couldn't be mapped.
+fn convert(source: &str) -> (Options, Vec<String>) {
+    let mut options = Options::default();
+    let mut unmapped = Vec::new();
+    // swaylock always shows its ring
+    options.indicator.style = Some(IndicatorStyle::Ring);
+
+    for line in source.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.trim_start_matches("--").split_once('=') {
+            Some((key, value)) => (key.trim(), Some(value.trim())),
+            None => (line.trim_start_matches("--"), None),
+        };
+
+        let mapped = match (key, value) {
+            ("color", Some(value)) => set_color(&mut options.colors.color_bg, value),
+            ("ring-color", Some(value)) => set_color(&mut options.colors.color_init, value),
+            // Also the bar color while typing, for when the bar is chosen instead
+            ("key-hl-color", Some(value)) => {
+                set_color(&mut options.colors.color_input, value)
+                    && set_color(&mut options.colors.color_key_highlight, value)
+            }
+            ("bs-hl-color", Some(value)) => {
+                set_color(&mut options.colors.color_backspace_highlight, value)
+            }
+            ("ring-wrong-color", Some(value)) => set_color(&mut options.colors.color_fail, value),
+            ("text-color", Some(value)) => set_color(&mut options.colors.color_text, value),
+            ("indicator-radius", Some(value)) => set_number(&mut options.indicator.radius, value),
+            ("indicator-thickness", Some(value)) => {
+                set_number(&mut options.indicator.thickness, value)
+            }
+            ("indicator-x-position", Some(value)) => set_number(&mut options.indicator.x, value),
+            ("indicator-y-position", Some(value)) => set_number(&mut options.indicator.y, value),
+            ("font", Some(value)) => {
+                options.font = Some(vec![FontSpec::from(value)]);
+                true
+            }
+            // Images for single outputs are written as `output:path`
+            ("image" | "i", Some(value)) if !image_for_output(value) => {
+                options.background.image = Some(PathBuf::from(value));
+                true
+            }
+            ("scaling", Some(value)) => match value.parse() {
+                Ok(mode) => {
+                    options.background.mode = Some(mode);
+                    true
+                }
+                // Like solid_color, which shows no image
+                Err(_) => false,
+            },
+            ("ignore-empty-password" | "e", None) => {
+                options.ignore_empty_password = Some(true);
+                true
+            }
+            ("show-failed-attempts" | "F", None) => {
+                options.show_failed_attempts = Some(true);
+                true
+            }
+            _ => false,
+        };
+        if !mapped {
+            unmapped.push(line.to_owned());
+        }
+    }
+
+    (options, unmapped)
+}
+
+/// Whether a swaylock image is only meant for one output, as in `eDP-1:~/wall.png`.
+fn image_for_output(value: &str) -> bool {
+    value.split_once(':').is_some_and(|(output, _)| !output.contains('/'))
+}
+
+/// swaylock colors are rrggbb or rrggbbaa, without a prefix.
+fn set_color(field: &mut Option<ColorSpec>, value: &str) -> bool {
+    match color::from_str(&format!("#{}", value.trim_start_matches('#'))) {
+        Ok(color) => {
+            *field = Some(ColorSpec::Color(color));
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+fn set_number(field: &mut Option<u32>, value: &str) -> bool {
+    match value.parse() {
+        Ok(number) => {
+            *field = Some(number);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::config::color::ColorSpec;
+    use crate::config::font::FontSpec;
+    use crate::config::{IndicatorStyle, ScaleMode};
+
+    #[test]
+    fn convert() {
+        let (options, unmapped) = super::convert(
+            "# swaylock config\ncolor=1d2021\nring-color=458588ff\nkey-hl-color=b8bb26\n\
+             ring-wrong-color=fb493480\ntext-color=ebdbb2\nfont=Hack\nignore-empty-password\n\
+             show-failed-attempts\nimage=~/wall.png\nscaling=fit\nimage=DP-1:/wall.png\n\
+             indicator-radius=100\nindicator-thickness=7\nbs-hl-color=db3300\n\
+             indicator-x-position=abc\ndaemonize\ninside-color=zzzzzz\n",
+        );
+        assert_eq!(options.colors.color_bg, Some(ColorSpec::Color(0xff1d2021)));
+        assert_eq!(options.colors.color_init, Some(ColorSpec::Color(0xff458588)));
+        assert_eq!(options.colors.color_input, Some(ColorSpec::Color(0xffb8bb26)));
+        assert_eq!(options.colors.color_key_highlight, Some(ColorSpec::Color(0xffb8bb26)));
+        assert_eq!(options.colors.color_backspace_highlight, Some(ColorSpec::Color(0xffdb3300)));
+        assert_eq!(options.colors.color_fail, Some(ColorSpec::Color(0x80fb4934)));
+        assert_eq!(options.colors.color_text, Some(ColorSpec::Color(0xffebdbb2)));
+        assert_eq!(options.font, Some(vec![FontSpec::from("Hack")]));
+        assert_eq!(options.ignore_empty_password, Some(true));
+        assert_eq!(options.show_failed_attempts, Some(true));
+        assert_eq!(options.background.image, Some(PathBuf::from("~/wall.png")));
+        assert_eq!(options.background.mode, Some(ScaleMode::Fit));
+        assert_eq!(options.indicator.style, Some(IndicatorStyle::Ring));
+        assert_eq!((options.indicator.radius, options.indicator.thickness), (Some(100), Some(7)));
+        assert_eq!(
+            unmapped,
+            vec![
+                "image=DP-1:/wall.png",
+                "indicator-x-position=abc",
+                "daemonize",
+                "inside-color=zzzzzz"
+            ]
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// How the lock state and the typing are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndicatorStyle {
+    /// A horizontal bar below the password feedback
+    Bar,
+    /// A ring around the clock, highlighting an arc on each keypress, like swaylock
+    Ring,
+}
+
+impl FromStr for IndicatorStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bar" => Ok(IndicatorStyle::Bar),
+            "ring" => Ok(IndicatorStyle::Ring),
+            _ => Err(format!("expected bar or ring, got \"{}\"", s)),
+        }
+    }
+}
+
+impl fmt::Display for IndicatorStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IndicatorStyle::Bar => "bar",
+            IndicatorStyle::Ring => "ring",
+        })
+    }
+}
@@ -0,0 +1,204 @@
+use std::path::Path;
+
+use serde::Deserialize;
+use toml::value::{Table, Value};
+
+use crate::config::{ConfigError, Options};
+
+/// Prefix of the environment variables overriding config keys. Nested keys are separated by a
+/// double underscore, e.g. `WAYLOCK_COLORS__COLOR_INIT`.
+pub const ENV_PREFIX: &str = "WAYLOCK_";
+
+/// Selects a profile instead of setting a key, see `Options::selected_profile`.
+pub const PROFILE_ENV: &str = "WAYLOCK_PROFILE";
+
+/// Merge `overlay` into `base`. Tables are merged recursively, every other value in `overlay`
+/// replaces the one in `base`.
+pub fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Table(base), Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Bring a layer into its canonical form by passing it through `Options`. This resolves key
+/// aliases, so layers using different names for a key still merge, and reports unknown keys.
+pub fn normalize(layer: Value, on_unknown: &mut dyn FnMut(String)) -> Result<Value, ConfigError> {
+    let options: Options = serde_ignored::deserialize(layer, |path| on_unknown(path.to_string()))
+        .map_err(ConfigError::Toml)?;
+    to_value(&options)
+}
+
+/// Parse a config file into a layer.
+pub fn from_file(path: &Path, on_unknown: &mut dyn FnMut(String)) -> Result<Value, ConfigError> {
+    let file = std::fs::read_to_string(path).map_err(ConfigError::IO)?;
+    let mut deserializer = toml::Deserializer::new(&file);
+    let options: Options =
+        serde_ignored::deserialize(&mut deserializer, |path| on_unknown(path.to_string()))
+            .map_err(ConfigError::Toml)?;
+    to_value(&options)
+}
+
+/// Build a layer from `WAYLOCK_*` environment variables. Values are parsed as TOML, anything
+/// that isn't valid TOML, or whose TOML type doesn't fit its key, is taken as a plain string.
+pub fn from_env<I: IntoIterator<Item = (String, String)>>(vars: I) -> Value {
+    let mut layer = Value::Table(Table::new());
+    for (name, raw) in vars {
+        if name == PROFILE_ENV {
+            continue;
+        }
+        let path = match name.strip_prefix(ENV_PREFIX) {
+            Some(path) if !path.is_empty() => path.to_lowercase(),
+            _ => continue,
+        };
+
+        let keys: Vec<_> = path.split("__").collect();
+        let mut value = nest(&keys, parse_value(&raw));
+        // `WAYLOCK_FAIL_COMMAND=true` is valid TOML, but only makes sense as a string
+        if normalize(value.clone(), &mut |_| {}).is_err() {
+            value = nest(&keys, Value::String(raw));
+        }
+        merge(&mut layer, value);
+    }
+    layer
+}
+
+/// Build a layer from `--set dotted.key=value` overrides.
+pub fn from_overrides(overrides: &[(String, Value)]) -> Value {
+    let mut layer = Value::Table(Table::new());
+    for (key, value) in overrides {
+        merge(&mut layer, nest(&key.split('.').collect::<Vec<_>>(), value.clone()));
+    }
+    layer
+}
+
+/// Parse a `dotted.key=value` override, the value like in [`from_env`].
+pub fn parse_override(s: &str) -> Result<(String, Value), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_owned(), parse_value(value.trim())))
+        }
+        _ => Err(format!("expected KEY=VALUE, got \"{}\"", s)),
+    }
+}
+
+/// Wrap `value` into a table for each of `keys`, the first key being the outermost.
+fn nest(keys: &[&str], value: Value) -> Value {
+    keys.iter().rev().fold(value, |value, key| {
+        let mut table = Table::new();
+        table.insert((*key).to_owned(), value);
+        Value::Table(table)
+    })
+}
+
+/// Parse a single TOML value, falling back to a string.
+pub fn parse_value(raw: &str) -> Value {
+    #[derive(Deserialize)]
+    struct Wrapper {
+        value: Value,
+    }
+
+    toml::from_str::<Wrapper>(&format!("value = {}", raw))
+        .map(|wrapper| wrapper.value)
+        .unwrap_or_else(|_| Value::String(raw.to_owned()))
+}
+
+pub fn to_value(options: &Options) -> Result<Value, ConfigError> {
+    Value::try_from(options).map_err(|err| ConfigError::Serde(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Value;
+
+    fn toml(s: &str) -> Value {
+        toml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn merge_overrides_values_and_keeps_the_rest() {
+        let mut base =
+            toml("font = 'a'\nmax_restarts = 1\n[colors]\ncolor_init = 1\ncolor_fail = 2");
+        super::merge(&mut base, toml("font = 'b'\n[colors]\ncolor_fail = 3"));
+        assert_eq!(
+            base,
+            toml("font = 'b'\nmax_restarts = 1\n[colors]\ncolor_init = 1\ncolor_fail = 3")
+        );
+    }
+
+    #[test]
+    fn env_vars_are_nested_and_typed() {
+        let layer = super::from_env(vec![
+            ("WAYLOCK_MAX_RESTARTS".to_owned(), "3".to_owned()),
+            ("WAYLOCK_FAIL_COMMAND".to_owned(), "echo failed".to_owned()),
+            ("WAYLOCK_COLORS__COLOR_INIT".to_owned(), "0x002b36".to_owned()),
+            ("HOME".to_owned(), "/home/user".to_owned()),
+            ("WAYLOCK_PROFILE".to_owned(), "office".to_owned()),
+        ]);
+        assert_eq!(
+            layer,
+            toml("max_restarts = 3\nfail_command = 'echo failed'\n[colors]\ncolor_init = 0x002b36")
+        );
+    }
+
+    #[test]
+    fn env_vars_of_the_wrong_type_are_strings() {
+        let layer = super::from_env(vec![
+            ("WAYLOCK_FAIL_COMMAND".to_owned(), "true".to_owned()),
+            ("WAYLOCK_MAX_RESTARTS".to_owned(), "1".to_owned()),
+        ]);
+        assert_eq!(layer, toml("fail_command = 'true'\nmax_restarts = 1"));
+        super::normalize(layer, &mut |_| {}).unwrap();
+    }
+
+    #[test]
+    fn overrides_are_nested_and_typed() {
+        let overrides: Vec<_> =
+            ["colors.color_bg=#000000", "max_restarts = 3", "font=Hack Nerd Font"]
+                .iter()
+                .map(|s| super::parse_override(s).unwrap())
+                .collect();
+        assert_eq!(
+            super::from_overrides(&overrides),
+            toml("max_restarts = 3\nfont = 'Hack Nerd Font'\n[colors]\ncolor_bg = '#000000'")
+        );
+        assert!(super::parse_override("max_restarts").is_err());
+        assert!(super::parse_override("=3").is_err());
+    }
+
+    #[test]
+    fn aliases_are_normalized() {
+        let layer = super::normalize(toml("[colors]\ninit_color = 1"), &mut |_| {}).unwrap();
+        assert_eq!(
+            layer.get("colors").and_then(|colors| colors.get("color_init")),
+            Some(&Value::String("#000001".to_owned()))
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_reported() {
+        let mut unknown = Vec::new();
+        super::normalize(toml("fail_comand = 'x'\n[colors]\ncolor_nope = 1"), &mut |key| {
+            unknown.push(key)
+        })
+        .unwrap();
+        assert_eq!(unknown, vec!["colors.color_nope".to_owned(), "fail_comand".to_owned()]);
+    }
+
+    #[test]
+    fn example_config_has_no_unknown_keys() {
+        let mut unknown = Vec::new();
+        let layer = toml(include_str!("../../waylock.toml"));
+        super::normalize(layer, &mut |key| unknown.push(key)).unwrap();
+        assert!(unknown.is_empty(), "{:?}", unknown);
+    }
+}
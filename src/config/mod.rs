@@ -1,17 +1,38 @@
+use std::convert::TryFrom;
 use std::fmt::{Display, Formatter};
 use std::string::ToString;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use fontdue::Font;
+use image::RgbaImage;
 use serde::de::{Error, StdError};
-use crate::config::options::Options;
-use crate::config::font::load_font;
+use crate::config::font::{FontSet, FontSpec};
 use std::io::ErrorKind;
 use std::env::VarError;
-use crate::config::color::Error as ColorError;
+use crate::config::color::{ColorSpec, Error as ColorError};
 
 mod color;
 mod options;
 mod font;
+mod layer;
+mod check;
+mod watch;
+mod theme;
+mod palette;
+mod import;
+mod background;
+mod indicator;
+
+pub use background::{
+    load_image, Effect, Gradient, GradientKind, Pattern, PatternKind, ScaleMode,
+    MAX_ANIMATION_BYTES,
+};
+pub use check::check_config;
+pub use indicator::IndicatorStyle;
+pub use import::import_swaylock;
+pub use watch::ConfigWatcher;
+pub use options::{warn_unknown, Command, Options};
 
 #[derive(Debug)]
 pub enum ConfigError {
@@ -20,7 +41,11 @@ pub enum ConfigError {
     Color(color::Error),
     Params(clap::Error),
     Env(std::env::VarError),
-    Serde(String)
+    Serde(String),
+    Theme(String),
+    Palette(String),
+    Profile(String),
+    User(String),
 }
 
 impl Display for ConfigError {
@@ -33,13 +58,15 @@ impl Display for ConfigError {
                 _ => f.write_str("An unknown issue occurred while trying to read the config file.")
             }
             ConfigError::Toml(err) => match err.line_col() {
-                None => f.write_str("Unknown error occurred while reading the configuration."),
+                None => f.write_str(&format!("Error occurred while reading the configuration: {}", err)),
                 Some((line, col)) => f.write_str(&format!("Error occurred while parsing the config file at line {}, column {}.", line + 1, col + 1)),
             }
             ConfigError::Color(err) => match err {
                 ColorError::InvalidLength => f.write_str("Error while parsing the flags. A color seems to have invalid length"),
                 ColorError::InvalidPrefix => f.write_str("Error while parsing the flags. A color seems to have an invalid prefix"),
-                ColorError::ParseInt(_) => f.write_str("Error while parsing the flags. A color seems to be ill formated."),
+                ColorError::InvalidFunction(_) => f.write_str("Error while parsing the flags. A color function seems to be ill formated."),
+                ColorError::OutOfRange => f.write_str("Error while parsing the flags. A color component seems to be out of range."),
+                ColorError::ParseInt(_) | ColorError::ParseFloat(_) => f.write_str("Error while parsing the flags. A color seems to be ill formated."),
             },
             ConfigError::Params(err) => f.write_str(&err.message),
             ConfigError::Env(err) => match err {
@@ -47,6 +74,8 @@ impl Display for ConfigError {
                 VarError::NotUnicode(_) => f.write_str("Either $XDG_CONFIG_HOME or $HOME are not in Unicode.")
             },
             ConfigError::Serde(err) => f.write_str(&format!("Could not parse the config file. Error: {}", err)),
+            ConfigError::Theme(err) | ConfigError::Palette(err) | ConfigError::User(err) => f.write_str(err),
+            ConfigError::Profile(name) => f.write_str(&format!("There is no [profile.{}] in the configuration.", name)),
         }
     }
 }
@@ -60,10 +89,29 @@ impl Error for ConfigError {
 }
 pub struct Config {
     pub fail_command: Option<String>,
-    pub font: [Font; 1],
+    /// Every font of the UI, starting with the embedded font.
+    pub font: Vec<Font>,
+    /// Names of the loaded fonts, in the order of `font`.
+    pub font_names: Vec<String>,
+    /// Fallback chains of the UI elements, as indices into `font`.
+    pub font_chains: FontChains,
+    pub background: Background,
+    pub indicator: Indicator,
     pub user: String,
     pub max_restarts: usize,
+    pub restart_window: Duration,
+    pub restart_backoff: Duration,
+    pub crash_command: Option<String>,
+    pub safe_mode: bool,
+    pub unlock_on_term: bool,
+    pub unlock_on_usr1: bool,
+    pub show_user: bool,
+    pub message: Option<String>,
+    pub ignore_empty_password: bool,
+    pub show_failed_attempts: bool,
     pub colors: Colors,
+    /// Config files the configuration was read from, watched for live reloading.
+    pub config_files: Vec<PathBuf>,
 }
 
 pub struct Colors {
@@ -72,36 +120,150 @@ pub struct Colors {
     pub fail_color: u32,
     pub bg_color: u32,
     pub text_color: u32,
+    pub key_highlight_color: u32,
+    pub backspace_highlight_color: u32,
+}
+
+pub struct Indicator {
+    pub style: IndicatorStyle,
+    /// The radius of the ring, to the middle of its line.
+    pub radius: u32,
+    pub thickness: u32,
+    /// The center of the ring, the center of the output where `None`.
+    pub x: Option<u32>,
+    pub y: Option<u32>,
+}
+
+pub struct Background {
+    /// The decoded image, `None` without one, if it could not be decoded or is a directory. The
+    /// first frame of an animated image.
+    pub image: Option<RgbaImage>,
+    /// The frames of an animated image and how long each is shown, empty for a still image.
+    pub frames: Vec<(RgbaImage, Duration)>,
+    /// How long an animation plays before it stops, to save power. Input plays it again.
+    pub pause_after: Option<Duration>,
+    /// The images of the directory given as the image, decoded when they are shown.
+    pub slideshow: Vec<PathBuf>,
+    /// How often the slideshow moves on, never without an interval.
+    pub interval: Option<Duration>,
+    pub fade: Duration,
+    pub mode: ScaleMode,
+    /// Whether to show captures of the outputs instead of the image.
+    pub screenshot: bool,
+    pub effects: Vec<Effect>,
+    pub gradient: Option<Gradient>,
+    pub dither: bool,
+    pub pattern: Option<Pattern>,
+}
+
+impl Background {
+    /// Whether nothing but `color_bg` is drawn when there is no image.
+    pub fn is_plain(&self) -> bool {
+        self.gradient.is_none() && self.pattern.is_none() && self.effects.is_empty()
+    }
+}
+
+/// Font chains of the UI elements, each ending with the embedded font.
+pub struct FontChains {
+    pub clock: Vec<usize>,
+    pub user: Vec<usize>,
+    pub password: Vec<usize>,
+    pub message: Vec<usize>,
+    pub banner: Vec<usize>,
 }
 
 impl Config {
     pub fn new() -> Result<Config, ConfigError> {
-        Ok(Options::new()?.into())
+        Config::try_from(Options::new()?)
     }
 }
 
-impl From<Options> for Config {
-    fn from(options: Options) -> Self {
-        let font = options.font.map(load_font)
-            .flatten()
-            .or_else(|| load_font("monospace"))
-            .expect("The default font is not available on the system.");
+impl TryFrom<Options> for Config {
+    type Error = ConfigError;
+
+    fn try_from(options: Options) -> Result<Self, ConfigError> {
+        let config_files = options.config_files();
+        let mut fonts = FontSet::new();
+        let default_font = options.font.unwrap_or_default();
+        let mut chain = |element: Option<Vec<FontSpec>>| {
+            fonts.chain(element.as_deref().unwrap_or(&default_font))
+        };
+        let font_chains = FontChains {
+            clock: chain(options.fonts.font_clock),
+            user: chain(options.fonts.font_user),
+            password: chain(options.fonts.font_password),
+            message: chain(options.fonts.font_message),
+            banner: chain(options.fonts.font_banner),
+        };
 
-        let user = users::get_current_username().expect("No user is running this command");
+        let user = users::get_current_username()
+            .ok_or_else(|| ConfigError::User("No user is running this command.".to_owned()))?
+            .into_string()
+            .map_err(|user| ConfigError::User(format!("The user name {:?} is not in Unicode.", user)))?;
 
-        Self {
+        let mut frames = match &options.background.image {
+            Some(path) if !background::is_directory(path) => background::load_frames(path),
+            _ => Vec::new(),
+        };
+        // Still images are drawn from `image` alone
+        let image = match frames.len() {
+            0 | 1 => frames.pop().map(|(image, _)| image),
+            _ => Some(frames[0].0.clone()),
+        };
+
+        Ok(Self {
             fail_command: options.fail_command,
-            font: [font],
-            user: user.into_string().expect("Username could not be fetched"),
+            safe_mode: options.safe_mode.unwrap_or(false),
+            font: fonts.fonts,
+            font_names: fonts.names,
+            font_chains,
+            background: Background {
+                image,
+                frames,
+                pause_after: options.background.pause_after,
+                slideshow: match &options.background.image {
+                    Some(dir) if background::is_directory(dir) => background::load_directory(dir),
+                    _ => Vec::new(),
+                },
+                // A zero interval would keep decoding images without a pause
+                interval: options.background.interval.filter(|interval| !interval.is_zero()),
+                fade: options.background.fade.unwrap_or(Duration::from_secs(1)),
+                mode: options.background.mode.unwrap_or(ScaleMode::Fill),
+                screenshot: options.background.screenshot.unwrap_or(false),
+                effects: options.background.effects.unwrap_or_default(),
+                gradient: options.background.gradient,
+                dither: options.background.dither.unwrap_or(false),
+                pattern: options.background.pattern,
+            },
+            indicator: Indicator {
+                style: options.indicator.style.unwrap_or(IndicatorStyle::Bar),
+                radius: options.indicator.radius.unwrap_or(50),
+                thickness: options.indicator.thickness.unwrap_or(10),
+                x: options.indicator.x,
+                y: options.indicator.y,
+            },
+            user,
             max_restarts: options.max_restarts.unwrap_or(5),
+            restart_window: options.restart_window.unwrap_or(Duration::from_secs(1)),
+            restart_backoff: options.restart_backoff.unwrap_or(Duration::from_millis(100)),
+            crash_command: options.crash_command,
+            unlock_on_term: options.unlock_on_term.unwrap_or(false),
+            unlock_on_usr1: options.unlock_on_usr1.unwrap_or(false),
+            show_user: options.show_user.unwrap_or(true),
+            message: options.message,
+            ignore_empty_password: options.ignore_empty_password.unwrap_or(false),
+            show_failed_attempts: options.show_failed_attempts.unwrap_or(false),
             colors: Colors {
-                init_color: options.colors.color_init.unwrap_or(0xffffffff) | 0xff000000,
-                input_color: options.colors.color_input.unwrap_or(0xff0000ff) | 0xff000000,
-                fail_color: options.colors.color_fail.unwrap_or(0xffff0000) | 0xff000000,
-                bg_color: options.colors.color_bg.unwrap_or(0xff000000) | 0xff000000,
-                text_color: options.colors.color_text.unwrap_or(0xffffffff) | 0xff000000,
-            }
-        }
+                init_color: options.colors.color_init.as_ref().and_then(ColorSpec::color).unwrap_or(0xffffffff),
+                input_color: options.colors.color_input.as_ref().and_then(ColorSpec::color).unwrap_or(0xff0000ff),
+                fail_color: options.colors.color_fail.as_ref().and_then(ColorSpec::color).unwrap_or(0xffff0000),
+                bg_color: options.colors.color_bg.as_ref().and_then(ColorSpec::color).unwrap_or(0xff000000),
+                text_color: options.colors.color_text.as_ref().and_then(ColorSpec::color).unwrap_or(0xffffffff),
+                key_highlight_color: options.colors.color_key_highlight.as_ref().and_then(ColorSpec::color).unwrap_or(0xff33db00),
+                backspace_highlight_color: options.colors.color_backspace_highlight.as_ref().and_then(ColorSpec::color).unwrap_or(0xffdb3300),
+            },
+            config_files,
+        })
     }
 }
 
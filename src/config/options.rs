@@ -1,14 +1,23 @@
 use std::ops::Add;
 use std::env;
 use std::path::PathBuf;
+use std::time::Duration;
 
-use serde::Deserialize;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use structopt::StructOpt;
 
-use crate::config::{color, ConfigError};
-use log::{error, LevelFilter};
+use crate::config::background::{Effect, Gradient, Pattern, ScaleMode};
+use crate::config::color::{self, ColorSpec};
+use crate::config::font::FontSpec;
+use crate::config::indicator::IndicatorStyle;
+use crate::config::{font, layer, palette, theme, ConfigError};
+use toml::value::{Table, Value};
+use log::{info, warn, LevelFilter};
 
-#[derive(Debug, StructOpt, Deserialize)]
+/// The built-in defaults, the lowest config layer.
+const DEFAULTS: &str = include_str!("defaults.toml");
+
+#[derive(Debug, Clone, Default, StructOpt, Deserialize, Serialize)]
 pub struct Options {
     #[structopt(long, verbatim_doc_comment)]
     /// Command to be executed on a wrong entry of a password
@@ -24,56 +33,408 @@ pub struct Options {
     /// Path to a config file. Default ~/.config/waylock/
     pub config: Option<PathBuf>,
 
-    #[structopt(long)]
-    /// Font for the GUI
-    pub font: Option<String>,
+    #[structopt(subcommand)]
+    #[serde(skip)]
+    pub command: Option<Command>,
+
+    #[structopt(long = "profile", env = "WAYLOCK_PROFILE", verbatim_doc_comment)]
+    #[serde(skip)]
+    /// Apply the settings of the [profile.<PROFILE>] table of the config.
+    pub selected_profile: Option<String>,
+
+    #[structopt(skip)]
+    #[serde(rename = "profile", default, skip_serializing_if = "Table::is_empty")]
+    /// Named sets of settings, applied over the rest of the config when selected.
+    pub profiles: Table,
+
+    #[structopt(long, verbatim_doc_comment)]
+    #[serde(skip)]
+    /// Print the capabilities of the compositor and the effective configuration, then exit.
+    pub diagnose: bool,
+
+    #[structopt(long, requires = "diagnose", verbatim_doc_comment)]
+    #[serde(skip)]
+    /// Print the diagnostics as JSON.
+    pub json: bool,
+
+    #[structopt(
+        long = "set",
+        value_name = "KEY=VALUE",
+        number_of_values = 1,
+        parse(try_from_str = layer::parse_override),
+        verbatim_doc_comment
+    )]
+    #[serde(skip)]
+    /// Override any config key, after every other setting. Nested keys are separated by dots, the
+    /// value is read like in the config file, e.g. --set colors.color_bg='"#000000"'.
+    /// Values that aren't valid TOML are taken as strings. Can be repeated.
+    pub set: Vec<(String, Value)>,
+
+    #[structopt(long, require_delimiter = true, parse(from_str), verbatim_doc_comment)]
+    #[serde(default, deserialize_with = "font::deserialize", serialize_with = "font::serialize")]
+    /// Fonts for the GUI, as families or paths to font files. Each character is drawn with the
+    /// first font containing it, falling back to a built-in font. Separated by commas.
+    pub font: Option<Vec<FontSpec>>,
 
     #[structopt(long, verbatim_doc_comment)]
-    /// Max restarts/seconds before it stops to restart.
+    /// Theme providing colors and font, overridden by any other setting. Either a built-in theme,
+    /// a theme in ~/.config/waylock/themes/<THEME>.toml or the path to a theme file.
+    pub theme: Option<String>,
+
+    #[structopt(long, verbatim_doc_comment)]
+    /// Palette the colors can refer to by slot, like color1 or base08. One of "pywal",
+    /// "xresources", "pywal:<FILE>", "base16:<FILE>", "xresources:<FILE>", or the path to a
+    /// pywal colors.json, a base16 YAML scheme or an Xresources file.
+    pub palette: Option<String>,
+
+    #[structopt(long, verbatim_doc_comment)]
+    /// Max restarts within the restart window before escalating.
     pub max_restarts: Option<usize>,
 
+    #[structopt(long, parse(try_from_str = humantime::parse_duration), verbatim_doc_comment)]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    /// Time window in which restarts are counted, e.g. "1s" or "500ms".
+    pub restart_window: Option<Duration>,
+
+    #[structopt(long, parse(try_from_str = humantime::parse_duration), verbatim_doc_comment)]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    /// Delay before a restart, multiplied by the restarts within the window.
+    pub restart_backoff: Option<Duration>,
+
+    #[structopt(long, verbatim_doc_comment)]
+    /// Command to be executed when the lock keeps crashing
+    pub crash_command: Option<String>,
+
+    #[structopt(long, verbatim_doc_comment)]
+    /// Only draw the state colors, without any text.
+    pub safe_mode: Option<bool>,
+
+    #[structopt(long, verbatim_doc_comment)]
+    /// Unlock on SIGTERM or SIGINT instead of ignoring them.
+    pub unlock_on_term: Option<bool>,
+
+    #[structopt(long, verbatim_doc_comment)]
+    /// Unlock on SIGUSR1, like swaylock does.
+    pub unlock_on_usr1: Option<bool>,
+
+    #[structopt(long, verbatim_doc_comment)]
+    /// Show the name of the user.
+    pub show_user: Option<bool>,
+
+    #[structopt(long, verbatim_doc_comment)]
+    /// Message shown below the password, e.g. contact information.
+    pub message: Option<String>,
+
+    #[structopt(long, verbatim_doc_comment)]
+    /// Don't try to authenticate when enter is pressed without a password.
+    pub ignore_empty_password: Option<bool>,
+
+    #[structopt(long, verbatim_doc_comment)]
+    /// Show the number of failed authentication attempts.
+    pub show_failed_attempts: Option<bool>,
+
     #[structopt(flatten, verbatim_doc_comment)]
     #[serde(default)]
     pub colors: Colors,
+
+    #[structopt(flatten, verbatim_doc_comment)]
+    #[serde(default, skip_serializing_if = "Fonts::is_empty")]
+    pub fonts: Fonts,
+
+    #[structopt(flatten, verbatim_doc_comment)]
+    #[serde(default)]
+    pub background: Background,
+
+    #[structopt(flatten, verbatim_doc_comment)]
+    #[serde(default)]
+    pub indicator: Indicator,
+}
+
+#[derive(Debug, Clone, StructOpt)]
+pub enum Command {
+    /// Check a config file for errors without locking the screen. Defaults to the user config.
+    CheckConfig {
+        #[structopt(parse(from_os_str))]
+        file: Option<PathBuf>,
+    },
+    /// Print the effective configuration, merged from every layer, as TOML.
+    PrintConfig,
+    /// Make the running waylock reload its configuration, without unlocking.
+    Reload,
+    /// Print a waylock.toml converted from a swaylock config. Defaults to the swaylock config in
+    /// the places swaylock looks for it.
+    ImportSwaylock {
+        #[structopt(parse(from_os_str))]
+        file: Option<PathBuf>,
+    },
 }
 
-#[derive(Debug, StructOpt, Deserialize, Default)]
+#[derive(Debug, Clone, StructOpt, Deserialize, Serialize, Default)]
 pub struct Colors {
-    #[structopt(short = "C", long, parse(try_from_str = color::from_str), verbatim_doc_comment)]
+    #[structopt(short = "C", long, parse(try_from_str = color::parse_spec), verbatim_doc_comment)]
+    #[serde(
+        alias = "init_color",
+        default,
+        deserialize_with = "color::deserialize",
+        serialize_with = "color::serialize"
+    )]
     /// Color of the GUI bar, when the lock is initialized
-    pub color_init: Option<u32>,
+    pub color_init: Option<ColorSpec>,
 
-    #[structopt(short = "C", long, parse(try_from_str = color::from_str), verbatim_doc_comment)]
+    #[structopt(long, parse(try_from_str = color::parse_spec), verbatim_doc_comment)]
+    #[serde(
+        alias = "input_color",
+        default,
+        deserialize_with = "color::deserialize",
+        serialize_with = "color::serialize"
+    )]
     /// Color of the GUI bar, during typing
-    pub color_input: Option<u32>,
+    pub color_input: Option<ColorSpec>,
 
-    #[structopt(long, parse(try_from_str = color::from_str), verbatim_doc_comment)]
+    #[structopt(long, parse(try_from_str = color::parse_spec), verbatim_doc_comment)]
+    #[serde(
+        alias = "fail_color",
+        default,
+        deserialize_with = "color::deserialize",
+        serialize_with = "color::serialize"
+    )]
     /// Color of the GUI bar, if the password was wrong
-    pub color_fail: Option<u32>,
+    pub color_fail: Option<ColorSpec>,
 
-    #[structopt(long, parse(try_from_str = color::from_str), verbatim_doc_comment)]
+    #[structopt(long, parse(try_from_str = color::parse_spec), verbatim_doc_comment)]
+    #[serde(
+        alias = "bg_color",
+        default,
+        deserialize_with = "color::deserialize",
+        serialize_with = "color::serialize"
+    )]
     /// Static background color of the GUI.
-    pub color_bg: Option<u32>,
+    pub color_bg: Option<ColorSpec>,
 
-    #[structopt(long, parse(try_from_str = color::from_str), verbatim_doc_comment)]
+    #[structopt(long, parse(try_from_str = color::parse_spec), verbatim_doc_comment)]
+    #[serde(
+        alias = "text_color",
+        default,
+        deserialize_with = "color::deserialize",
+        serialize_with = "color::serialize"
+    )]
     /// Color of the text displayed
-    pub color_text: Option<u32>,
+    pub color_text: Option<ColorSpec>,
+
+    #[structopt(long, parse(try_from_str = color::parse_spec), verbatim_doc_comment)]
+    #[serde(default, deserialize_with = "color::deserialize", serialize_with = "color::serialize")]
+    /// Color of the arc of the ring highlighted on each keypress
+    pub color_key_highlight: Option<ColorSpec>,
+
+    #[structopt(long, parse(try_from_str = color::parse_spec), verbatim_doc_comment)]
+    #[serde(default, deserialize_with = "color::deserialize", serialize_with = "color::serialize")]
+    /// Color of the arc of the ring highlighted on backspace
+    pub color_backspace_highlight: Option<ColorSpec>,
 }
 
-fn default_config_path() -> Result<PathBuf, ConfigError> {
+// Fonts of single UI elements, in the same forms as `font`, which they override. Not a doc
+// comment, structopt would show it as the about text of waylock.
+#[derive(Debug, Clone, StructOpt, Deserialize, Serialize, Default)]
+pub struct Fonts {
+    #[structopt(long, require_delimiter = true, parse(from_str), verbatim_doc_comment)]
+    #[serde(default, deserialize_with = "font::deserialize", serialize_with = "font::serialize")]
+    /// Fonts of the clock
+    pub font_clock: Option<Vec<FontSpec>>,
+
+    #[structopt(long, require_delimiter = true, parse(from_str), verbatim_doc_comment)]
+    #[serde(default, deserialize_with = "font::deserialize", serialize_with = "font::serialize")]
+    /// Fonts of the user name
+    pub font_user: Option<Vec<FontSpec>>,
+
+    #[structopt(long, require_delimiter = true, parse(from_str), verbatim_doc_comment)]
+    #[serde(default, deserialize_with = "font::deserialize", serialize_with = "font::serialize")]
+    /// Fonts of the password feedback and the failed attempts
+    pub font_password: Option<Vec<FontSpec>>,
+
+    #[structopt(long, require_delimiter = true, parse(from_str), verbatim_doc_comment)]
+    #[serde(default, deserialize_with = "font::deserialize", serialize_with = "font::serialize")]
+    /// Fonts of the message
+    pub font_message: Option<Vec<FontSpec>>,
+
+    #[structopt(long, require_delimiter = true, parse(from_str), verbatim_doc_comment)]
+    #[serde(default, deserialize_with = "font::deserialize", serialize_with = "font::serialize")]
+    /// Fonts of the banner shown when the config could not be reloaded
+    pub font_banner: Option<Vec<FontSpec>>,
+}
+
+// What is drawn below the UI, on top of `color_bg`.
+#[derive(Debug, Clone, StructOpt, Deserialize, Serialize, Default)]
+pub struct Background {
+    #[structopt(long = "background-image", parse(from_os_str), verbatim_doc_comment)]
+    /// PNG, JPEG or GIF image shown as the background, or a directory of them to pick from.
+    /// Animated GIF and PNG images are played.
+    pub image: Option<PathBuf>,
+
+    #[structopt(long = "background-mode", verbatim_doc_comment)]
+    /// How the image is scaled to each output: fill, fit, center, tile or stretch
+    pub mode: Option<ScaleMode>,
+
+    #[structopt(long = "background-screenshot", verbatim_doc_comment)]
+    /// Show what each output showed right before locking, instead of the image. Needs a
+    /// compositor supporting wlr-screencopy.
+    pub screenshot: Option<bool>,
+
+    #[structopt(long = "background-effect", number_of_values = 1, verbatim_doc_comment)]
+    /// Effects applied to the background in order, like gaussian_blur:8, pixelate:16, darken:0.3,
+    /// brightness:0.1, contrast:1.2, grayscale:1, vignette:0.5, tint:#28283780 or noise:0.05.
+    /// Can be repeated.
+    pub effects: Option<Vec<Effect>>,
+
+    #[structopt(long = "background-gradient", verbatim_doc_comment)]
+    /// Gradient drawn over color_bg, like linear:135:#1e1e2e,#89b4fa 60%,#f38ba8 with an angle in
+    /// degrees, or radial:#313244,#11111b.
+    pub gradient: Option<Gradient>,
+
+    #[structopt(long = "background-dither", verbatim_doc_comment)]
+    /// Dither the gradient, which hides banding between close colors.
+    pub dither: Option<bool>,
+
+    #[structopt(long = "background-pattern", verbatim_doc_comment)]
+    /// Pattern drawn over the gradient, like checker:32:#ffffff10, stripes:16:#00000020 or
+    /// dots:24:#ffffff18, with a size in pixels.
+    pub pattern: Option<Pattern>,
+
+    #[structopt(
+        long = "background-interval",
+        parse(try_from_str = humantime::parse_duration),
+        verbatim_doc_comment
+    )]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    /// With a directory as the image, how often to move on to another image, e.g. "5m". Without
+    /// it each output keeps the image picked at random when it was locked.
+    pub interval: Option<Duration>,
+
+    #[structopt(
+        long = "background-fade",
+        parse(try_from_str = humantime::parse_duration),
+        verbatim_doc_comment
+    )]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    /// How long the images of a directory cross-fade, e.g. "1s". "0s" switches at once.
+    pub fade: Option<Duration>,
+
+    #[structopt(
+        long = "background-pause-after",
+        parse(try_from_str = humantime::parse_duration),
+        verbatim_doc_comment
+    )]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    /// Stop an animated image after it played this long, e.g. "30s", to save power. Typing plays
+    /// it again.
+    pub pause_after: Option<Duration>,
+}
+
+// How the lock state and typing are shown, by the `[indicator]` table.
+#[derive(Debug, Clone, StructOpt, Deserialize, Serialize, Default)]
+pub struct Indicator {
+    #[structopt(long = "indicator-style", verbatim_doc_comment)]
+    /// Show the state as a bar below the password, or as a ring around the clock: bar or ring
+    pub style: Option<IndicatorStyle>,
+
+    #[structopt(long = "indicator-radius", verbatim_doc_comment)]
+    /// Radius of the ring in pixels, to the middle of its line
+    pub radius: Option<u32>,
+
+    #[structopt(long = "indicator-thickness", verbatim_doc_comment)]
+    /// Thickness of the line of the ring in pixels
+    pub thickness: Option<u32>,
+
+    #[structopt(long = "indicator-x", verbatim_doc_comment)]
+    /// Horizontal position of the center of the ring in pixels, centered on each output without it
+    pub x: Option<u32>,
+
+    #[structopt(long = "indicator-y", verbatim_doc_comment)]
+    /// Vertical position of the center of the ring in pixels, centered on each output without it
+    pub y: Option<u32>,
+}
+
+impl Fonts {
+    fn is_empty(&self) -> bool {
+        self.font_clock.is_none()
+            && self.font_user.is_none()
+            && self.font_password.is_none()
+            && self.font_message.is_none()
+            && self.font_banner.is_none()
+    }
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|duration| humantime::parse_duration(&duration).map_err(de::Error::custom))
+        .transpose()
+}
+
+fn serialize_duration<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    duration.map(|duration| humantime::format_duration(duration).to_string()).serialize(serializer)
+}
+
+/// The user config directory, `$XDG_CONFIG_HOME/waylock`.
+pub(super) fn config_dir() -> Result<PathBuf, ConfigError> {
     let home = |_| env::var("HOME").map(|v| v.add("/.config"));
     env::var("XDG_CONFIG_HOME")
         .or_else(home)
-        .map(|path| path.add("/waylock/waylock.toml"))
-        .map(PathBuf::from)
+        .map(|path| PathBuf::from(path).join("waylock"))
         .map_err(ConfigError::Env)
 }
 
+pub(super) fn default_config_path() -> Result<PathBuf, ConfigError> {
+    config_dir().map(|dir| dir.join("waylock.toml"))
+}
+
+/// System wide config files, ordered from the lowest to the highest precedence.
+fn system_config_paths() -> Vec<PathBuf> {
+    let dirs = env::var("XDG_CONFIG_DIRS").unwrap_or_default();
+    let dirs = if dirs.is_empty() { "/etc/xdg".to_owned() } else { dirs };
+    dirs.rsplit(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir).join("waylock/waylock.toml"))
+        .collect()
+}
 
+/// Report an unknown key, which is ignored otherwise.
+pub fn warn_unknown(source: &str, key: &str) {
+    eprintln!("Ignoring unknown config key `{}` in {}", key, source);
+    warn!("Ignoring unknown config key `{}` in {}", key, source);
+}
 
 impl Options {
     pub fn new() -> Result<Options, ConfigError> {
-        let mut cmd_params: Options = Options::from_args_safe().map_err(ConfigError::Params)?;
+        Self::layered(Self::parse_args()?, &mut warn_unknown)
+    }
+
+    /// Parse the command line parameters only and set up logging accordingly.
+    pub fn parse_args() -> Result<Options, ConfigError> {
+        let cmd_params: Options = Options::from_args_safe().map_err(ConfigError::Params)?;
 
         let _ = crate::logger::Logger::init(match cmd_params.log_level {
             0 => LevelFilter::Off,
@@ -84,34 +445,103 @@ impl Options {
             _ => LevelFilter::Trace,
         });
 
-        let config_path = match cmd_params.config.clone() {
-            None => default_config_path()?,
-            Some(config) => config
+        Ok(cmd_params)
+    }
+
+    /// Write the options as a TOML document. Goes through a `Value`, which puts arrays of tables
+    /// like font chains after the plain values, as TOML requires.
+    pub fn to_toml(&self) -> Result<String, ConfigError> {
+        toml::to_string(&layer::to_value(self)?).map_err(|err| ConfigError::Serde(err.to_string()))
+    }
+
+    /// Config files `layered` reads, whether they exist or not, lowest precedence first.
+    pub fn config_files(&self) -> Vec<PathBuf> {
+        let mut files = system_config_paths();
+        files.extend(self.config.clone().or_else(|| default_config_path().ok()));
+        files.extend(self.theme.as_deref().and_then(theme::path));
+        files
+    }
+
+    /// Merge every config layer below the command line parameters into them. Layers from lowest
+    /// to highest precedence: the built-in defaults, the theme, the system config files, the user
+    /// config file, the selected profile, `WAYLOCK_*` environment variables, the command line and
+    /// finally `--set` overrides.
+    ///
+    /// `on_unknown` is called with the layer and the key of every key that isn't known.
+    pub fn layered(
+        cmd_params: Options,
+        on_unknown: &mut dyn FnMut(&str, &str),
+    ) -> Result<Options, ConfigError> {
+        let mut merged = Value::Table(Table::new());
+
+        for path in system_config_paths().into_iter().filter(|path| path.exists()) {
+            let source = path.display().to_string();
+            let file = layer::from_file(&path, &mut |key| on_unknown(&source, &key))?;
+            layer::merge(&mut merged, file);
+        }
+
+        let config_path = match &cmd_params.config {
+            // An explicitly given config file has to exist
+            Some(config) => Some(config.clone()),
+            None => Some(default_config_path()?).filter(|path| path.exists()),
         };
+        match config_path {
+            Some(path) => {
+                let source = path.display().to_string();
+                let file = layer::from_file(&path, &mut |key| on_unknown(&source, &key))?;
+                layer::merge(&mut merged, file);
+            }
+            None => info!("No user configuration file found"),
+        }
 
-        if config_path.exists() {
-            let file = std::fs::read_to_string(config_path).map_err(ConfigError::IO)?;
-            let config_file_options = toml::from_str(&file).map_err(ConfigError::Toml)?;
-            cmd_params = cmd_params.or(config_file_options);
-        } else {
-            error!("Could not load a configuration file");
+        // Profiles override the files they are defined in, but not the environment or command line
+        let mut profiles = match &mut merged {
+            Value::Table(table) => table.remove("profile"),
+            _ => None,
+        };
+        if let Some(name) = &cmd_params.selected_profile {
+            let profile = profiles
+                .as_mut()
+                .and_then(|profiles| profiles.as_table_mut())
+                .and_then(|profiles| profiles.remove(name))
+                .ok_or_else(|| ConfigError::Profile(name.clone()))?;
+            let source = format!("profile {}", name);
+            let profile = layer::normalize(profile, &mut |key| on_unknown(&source, &key))?;
+            layer::merge(&mut merged, profile);
         }
 
-        Ok(cmd_params)
-    }
+        let env = layer::from_env(env::vars());
+        let env = layer::normalize(env, &mut |key| {
+            let var = key.replace('.', "__").to_uppercase();
+            on_unknown("the environment", &format!("{}{}", layer::ENV_PREFIX, var))
+        })?;
+        layer::merge(&mut merged, env);
 
-    fn or(mut self, other: Self) -> Self {
-        if self.fail_command.is_none() { self.fail_command = other.fail_command; }
-        if self.font.is_none() { self.font = other.font; }
+        layer::merge(&mut merged, layer::to_value(&cmd_params)?);
 
-        if self.colors.color_init.is_none() { self.colors.color_init = other.colors.color_init; }
-        if self.colors.color_input.is_none() { self.colors.color_input = other.colors.color_input; }
-        if self.colors.color_fail.is_none() { self.colors.color_fail = other.colors.color_fail; }
+        let overrides = layer::from_overrides(&cmd_params.set);
+        let overrides = layer::normalize(overrides, &mut |key| on_unknown("--set", &key))?;
+        layer::merge(&mut merged, overrides);
 
-        if self.colors.color_bg.is_none() { self.colors.color_bg = other.colors.color_bg; }
-        if self.colors.color_text.is_none() { self.colors.color_text = other.colors.color_text; }
+        // The theme is chosen by the layers above it, so it is only merged in now
+        let defaults = toml::from_str(DEFAULTS).map_err(ConfigError::Toml)?;
+        let mut options = layer::normalize(defaults, &mut |key| on_unknown("the defaults", &key))?;
+        if let Some(name) = merged.get("theme").and_then(Value::as_str) {
+            layer::merge(&mut options, theme::load(name, on_unknown)?);
+        }
+        layer::merge(&mut options, merged);
 
-        self
+        let mut options: Options = options.try_into().map_err(ConfigError::Toml)?;
+        palette::resolve(&mut options.colors, options.palette.as_deref())?;
+        Ok(Options {
+            log_level: cmd_params.log_level,
+            config: cmd_params.config,
+            command: cmd_params.command,
+            selected_profile: cmd_params.selected_profile,
+            diagnose: cmd_params.diagnose,
+            json: cmd_params.json,
+            set: cmd_params.set,
+            ..options
+        })
     }
 }
-
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::config::color::{self, ColorSpec};
+use crate::config::options::Colors;
+use crate::config::ConfigError;
+
+/// Colors by slot name, like `color1`, `base08` or `background`. Names are lowercase.
+pub type Palette = HashMap<String, u32>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Pywal,
+    Base16,
+    Xresources,
+}
+
+/// Replace every palette slot in `colors` by its color from `palette`.
+pub fn resolve(colors: &mut Colors, palette: Option<&str>) -> Result<(), ConfigError> {
+    let mut fields = [
+        &mut colors.color_init,
+        &mut colors.color_input,
+        &mut colors.color_fail,
+        &mut colors.color_bg,
+        &mut colors.color_text,
+        &mut colors.color_key_highlight,
+        &mut colors.color_backspace_highlight,
+    ];
+    let first_slot = fields.iter().find_map(|field| match field {
+        Some(ColorSpec::Slot(slot)) => Some(slot.clone()),
+        _ => None,
+    });
+    let first_slot = match first_slot {
+        Some(slot) => slot,
+        None => return Ok(()),
+    };
+
+    let palette = match palette {
+        Some(palette) => load(palette)?,
+        None => {
+            return Err(ConfigError::Palette(format!(
+                "`{}` is neither a color nor usable as a palette color, as no palette is set.",
+                first_slot
+            )))
+        }
+    };
+
+    for field in fields.iter_mut() {
+        if let Some(ColorSpec::Slot(slot)) = field {
+            let color = palette.get(slot.as_str()).ok_or_else(|| {
+                ConfigError::Palette(format!("The palette has no color `{}`.", slot))
+            })?;
+            **field = Some(ColorSpec::Color(*color));
+        }
+    }
+    Ok(())
+}
+
+/// Load a palette. See `Options::palette` for the accepted forms of `spec`.
+pub fn load(spec: &str) -> Result<Palette, ConfigError> {
+    let (format, path) = match spec.split_once(':') {
+        Some(("pywal", path)) => (Format::Pywal, expand_home(path)),
+        Some(("base16", path)) => (Format::Base16, expand_home(path)),
+        Some(("xresources", path)) => (Format::Xresources, expand_home(path)),
+        _ => match spec {
+            "pywal" => (Format::Pywal, cache_dir().join("wal/colors.json")),
+            "xresources" => (Format::Xresources, home().join(".Xresources")),
+            path => {
+                let path = expand_home(path);
+                let format = match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("json") => Format::Pywal,
+                    Some("yaml") | Some("yml") => Format::Base16,
+                    _ => Format::Xresources,
+                };
+                (format, path)
+            }
+        },
+    };
+
+    let source = std::fs::read_to_string(&path).map_err(|err| {
+        ConfigError::Palette(format!("Could not read the palette {}: {}", path.display(), err))
+    })?;
+    let palette = match format {
+        Format::Pywal => parse_pywal(&source)?,
+        Format::Base16 => parse_base16(&source),
+        Format::Xresources => parse_xresources(&source),
+    };
+    if palette.is_empty() {
+        return Err(ConfigError::Palette(format!(
+            "The palette {} does not contain any colors.",
+            path.display()
+        )));
+    }
+    Ok(palette)
+}
+
+/// Parse the `colors.json` written by pywal, with its `special` and `colors` objects.
+fn parse_pywal(source: &str) -> Result<Palette, ConfigError> {
+    let json: serde_json::Value = serde_json::from_str(source)
+        .map_err(|err| ConfigError::Palette(format!("Invalid pywal palette: {}", err)))?;
+
+    let mut palette = Palette::new();
+    for group in &["special", "colors"] {
+        if let Some(colors) = json.get(group).and_then(|colors| colors.as_object()) {
+            for (name, value) in colors {
+                if let Some(color) = value.as_str().and_then(|value| color::from_str(value).ok()) {
+                    palette.insert(name.to_ascii_lowercase(), color);
+                }
+            }
+        }
+    }
+    Ok(palette)
+}
+
+/// Parse the `baseXX` entries of a base16 scheme. Only the flat `key: value` lines are needed, so
+/// this is not a full YAML parser.
+fn parse_base16(source: &str) -> Palette {
+    source
+        .lines()
+        .filter_map(|line| line.trim().split_once(':'))
+        .filter(|(key, _)| key.len() == 6 && key.starts_with("base"))
+        .filter_map(|(key, value)| {
+            // YAML comments start with a # after whitespace, unlike the # of a color
+            let value = value.split(" #").next().unwrap_or(value);
+            let value = value.trim().trim_matches(['"', '\'']);
+            // Older schemes write the digits without a prefix
+            let value = value.trim_start_matches('#');
+            let color = color::from_str(&format!("#{}", value)).ok()?;
+            Some((key.to_ascii_lowercase(), color))
+        })
+        .collect()
+}
+
+/// Parse the color resources of an Xresources file, like `*.color1: #cc241d`.
+fn parse_xresources(source: &str) -> Palette {
+    source
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('!') && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(':'))
+        .filter_map(|(resource, value)| {
+            let name = resource.rsplit(['.', '*']).next()?.trim();
+            let value = value.trim();
+            let color = match value.strip_prefix("rgb:") {
+                // The X11 form rgb:rr/gg/bb
+                Some(rgb) => color::from_str(&format!("#{}", rgb.replace('/', ""))).ok()?,
+                None => color::from_str(value).ok()?,
+            };
+            Some((name.to_ascii_lowercase(), color))
+        })
+        .collect()
+}
+
+fn home() -> PathBuf {
+    env::var_os("HOME").map(PathBuf::from).unwrap_or_default()
+}
+
+fn cache_dir() -> PathBuf {
+    env::var_os("XDG_CACHE_HOME").map(PathBuf::from).unwrap_or_else(|| home().join(".cache"))
+}
+
+pub(super) fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(path) => home().join(path),
+        None => Path::new(path).to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::color::ColorSpec;
+    use crate::config::options::Colors;
+
+    #[test]
+    fn pywal() {
+        let palette = super::parse_pywal(
+            r##"{
+                "wallpaper": "/tmp/wall.png",
+                "special": { "background": "#0b0c10", "foreground": "#c5c6c7", "cursor": "#c5c6c7" },
+                "colors": { "color0": "#0b0c10", "color1": "#66fcf1" }
+            }"##,
+        )
+        .unwrap();
+        assert_eq!(palette.get("background"), Some(&0xff0b0c10));
+        assert_eq!(palette.get("color1"), Some(&0xff66fcf1));
+        assert_eq!(palette.len(), 5);
+    }
+
+    #[test]
+    fn base16() {
+        let palette = super::parse_base16(
+            "scheme: \"Gruvbox dark\"\nauthor: \"Dawid Kurek\"\nbase00: \"282828\" # background\n\
+             palette:\n  base08: '#fb4934'\n  base0A: fabd2f\n",
+        );
+        assert_eq!(palette.get("base00"), Some(&0xff282828));
+        assert_eq!(palette.get("base08"), Some(&0xfffb4934));
+        assert_eq!(palette.get("base0a"), Some(&0xfffabd2f));
+        assert_eq!(palette.len(), 3);
+    }
+
+    #[test]
+    fn xresources() {
+        let palette = super::parse_xresources(
+            "! comment\n#define fg #ffffff\n*.foreground: #c5c8c6\n*color1:\t#cc6666\n\
+             URxvt.color2: rgb:b5/bd/68\nURxvt.font: xft:Hack:size=10\n",
+        );
+        assert_eq!(palette.get("foreground"), Some(&0xffc5c8c6));
+        assert_eq!(palette.get("color1"), Some(&0xffcc6666));
+        assert_eq!(palette.get("color2"), Some(&0xffb5bd68));
+        assert_eq!(palette.len(), 3);
+    }
+
+    #[test]
+    fn resolve() {
+        let path = std::env::temp_dir().join(format!("waylock-palette-{}", std::process::id()));
+        std::fs::write(&path, "*.color1: #cc6666\n").unwrap();
+
+        let mut colors = Colors {
+            color_init: Some(ColorSpec::Slot("color1".to_owned())),
+            color_fail: Some(ColorSpec::Color(0xffff0000)),
+            ..Colors::default()
+        };
+        let resolved = super::resolve(&mut colors, path.to_str());
+        let missing = super::resolve(
+            &mut Colors {
+                color_bg: Some(ColorSpec::Slot("color9".to_owned())),
+                ..Colors::default()
+            },
+            path.to_str(),
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(resolved.is_ok());
+        assert_eq!(colors.color_init, Some(ColorSpec::Color(0xffcc6666)));
+        assert_eq!(colors.color_fail, Some(ColorSpec::Color(0xffff0000)));
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn slots_need_a_palette() {
+        let mut colors =
+            Colors { color_init: Some(ColorSpec::Slot("color1".to_owned())), ..Colors::default() };
+        assert!(super::resolve(&mut colors, None).is_err());
+        assert!(super::resolve(&mut Colors::default(), None).is_ok());
+    }
+}
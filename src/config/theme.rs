@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+
+use toml::Value;
+
+use crate::config::options::config_dir;
+use crate::config::{layer, ConfigError};
+
+/// Themes embedded in the binary, by name.
+pub const PRESETS: &[(&str, &str)] = &[
+    ("dracula", include_str!("themes/dracula.toml")),
+    ("gruvbox-dark", include_str!("themes/gruvbox-dark.toml")),
+    ("nord", include_str!("themes/nord.toml")),
+    ("solarized-dark", include_str!("themes/solarized-dark.toml")),
+    ("solarized-light", include_str!("themes/solarized-light.toml")),
+];
+
+/// Keys a theme may set. Everything else, like commands, stays up to the config itself.
+const THEME_KEYS: &[&str] = &["background", "colors", "font", "fonts", "indicator", "palette"];
+
+/// The file a theme is read from. `None` for built-in themes and themes that don't exist.
+pub fn path(theme: &str) -> Option<PathBuf> {
+    if theme.contains('/') || theme.ends_with(".toml") {
+        return Some(PathBuf::from(theme));
+    }
+    // User themes shadow the built-in ones
+    config_dir()
+        .ok()
+        .map(|dir| dir.join("themes").join(format!("{}.toml", theme)))
+        .filter(|path| path.exists())
+}
+
+/// Load a theme as a config layer. `theme` is either the name of a theme in
+/// `$XDG_CONFIG_HOME/waylock/themes`, a built-in theme or the path to a theme file.
+pub fn load(theme: &str, on_unknown: &mut dyn FnMut(&str, &str)) -> Result<Value, ConfigError> {
+    let layer = match path(theme) {
+        Some(path) => {
+            let source = format!("theme {}", path.display());
+            layer::from_file(&path, &mut |key| on_unknown(&source, &key))?
+        }
+        None => {
+            let preset = PRESETS
+                .iter()
+                .find(|(name, _)| *name == theme)
+                .map(|(_, preset)| *preset)
+                .ok_or_else(|| ConfigError::Theme(unknown(theme)))?;
+            let preset = toml::from_str(preset).map_err(ConfigError::Toml)?;
+            let source = format!("theme {}", theme);
+            layer::normalize(preset, &mut |key| on_unknown(&source, &key))?
+        }
+    };
+
+    let forbidden: Vec<&String> = match &layer {
+        Value::Table(table) => {
+            table.keys().filter(|key| !THEME_KEYS.contains(&key.as_str())).collect()
+        }
+        _ => Vec::new(),
+    };
+    if !forbidden.is_empty() {
+        return Err(ConfigError::Theme(format!(
+            "Theme {} sets {:?}, but themes may only set {}.",
+            theme,
+            forbidden,
+            THEME_KEYS.join(", ")
+        )));
+    }
+
+    Ok(layer)
+}
+
+fn unknown(theme: &str) -> String {
+    let presets: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+    let user_themes = config_dir().map(|dir| dir.join("themes")).unwrap_or_default();
+    format!(
+        "Unknown theme {}. It is neither in {} nor one of the built-in themes: {}.",
+        theme,
+        user_themes.display(),
+        presets.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn presets_are_valid() {
+        for (name, _) in super::PRESETS {
+            let mut unknown = Vec::new();
+            let theme = super::load(name, &mut |_, key| unknown.push(key.to_owned())).unwrap();
+            assert!(unknown.is_empty(), "{}: {:?}", name, unknown);
+            assert!(theme.get("colors").is_some(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn themes_may_not_run_commands() {
+        let path = std::env::temp_dir().join(format!("waylock-theme-{}.toml", std::process::id()));
+        std::fs::write(&path, "fail_command = 'true'\n[colors]\ncolor_bg = 'black'").unwrap();
+        let theme = super::load(path.to_str().unwrap(), &mut |_, _| {});
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(theme, Err(crate::config::ConfigError::Theme(_))));
+    }
+
+    #[test]
+    fn unknown_themes_are_errors() {
+        assert!(super::load("no-such-theme", &mut |_, _| {}).is_err());
+    }
+}
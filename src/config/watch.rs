@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::PathBuf;
+
+use log::{debug, warn};
+use nix::errno::Errno;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+
+/// Watches config files through inotify. The directories are watched instead of the files, so
+/// files that are replaced on save, created or deleted are noticed as well.
+pub struct ConfigWatcher {
+    inotify: Inotify,
+    files: HashMap<WatchDescriptor, Vec<OsString>>,
+}
+
+impl ConfigWatcher {
+    pub fn new(paths: &[PathBuf]) -> nix::Result<Self> {
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
+        let flags = AddWatchFlags::IN_CLOSE_WRITE
+            | AddWatchFlags::IN_MOVED_TO
+            | AddWatchFlags::IN_MOVED_FROM
+            | AddWatchFlags::IN_DELETE;
+
+        let mut files: HashMap<WatchDescriptor, Vec<OsString>> = HashMap::new();
+        for path in paths {
+            let (dir, name) = match (path.parent(), path.file_name()) {
+                (Some(dir), Some(name)) => (dir, name),
+                _ => continue,
+            };
+            match inotify.add_watch(dir, flags) {
+                Ok(wd) => files.entry(wd).or_default().push(name.to_owned()),
+                Err(err) => debug!("Not watching {} for changes: {}", dir.display(), err),
+            }
+        }
+
+        Ok(Self { inotify, files })
+    }
+
+    /// Consume the pending events and return whether any watched file changed.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        loop {
+            match self.inotify.read_events() {
+                Ok(events) => {
+                    changed |= events.iter().any(|event| {
+                        let names = self.files.get(&event.wd);
+                        matches!((names, &event.name), (Some(names), Some(name)) if names.contains(name))
+                    })
+                }
+                Err(Errno::EAGAIN) => return changed,
+                Err(err) => {
+                    warn!("Could not read config file changes: {}", err);
+                    return changed;
+                }
+            }
+        }
+    }
+}
+
+impl AsRawFd for ConfigWatcher {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inotify.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::ConfigWatcher;
+
+    #[test]
+    fn only_watched_files_are_reported() {
+        let dir = std::env::temp_dir().join(format!("waylock-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let watcher = ConfigWatcher::new(&[dir.join("waylock.toml")]).unwrap();
+
+        fs::write(dir.join("other.toml"), "").unwrap();
+        assert!(!watcher.changed());
+
+        // Editors often save by writing a new file and renaming it over the old one
+        fs::write(dir.join("waylock.toml.swp"), "font = 'monospace'").unwrap();
+        fs::rename(dir.join("waylock.toml.swp"), dir.join("waylock.toml")).unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
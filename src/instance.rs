@@ -0,0 +1,179 @@
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use nix::errno::Errno;
+use nix::fcntl::{flock, FlockArg};
+use nix::sys::signal::kill;
+use nix::unistd::{getpid, getuid, Pid};
+
+/// Guard ensuring only one waylock supervisor runs per user. The lock is held through `flock` on
+/// a file in `$XDG_RUNTIME_DIR`, which also stores the pid of the supervisor holding it.
+pub struct InstanceLock {
+    file: File,
+    path: PathBuf,
+}
+
+pub enum Acquire {
+    /// No other instance is running, the lock is now held by this process.
+    Acquired(InstanceLock),
+    /// Another instance holds the lock. Contains its supervisor pid, if it is still alive.
+    Running(Option<Pid>),
+}
+
+impl InstanceLock {
+    pub fn acquire() -> io::Result<Acquire> {
+        Self::acquire_at(lock_path())
+    }
+
+    fn acquire_at(path: PathBuf) -> io::Result<Acquire> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(&path)?;
+
+        match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(()) => {}
+            Err(Errno::EWOULDBLOCK) => {
+                return match read_pid(&mut file)? {
+                    Some(pid) if is_alive(pid) => Ok(Acquire::Running(Some(pid))),
+                    pid => {
+                        // The lock file descriptor is inherited by the lock child, so it can
+                        // outlive a crashed supervisor. The screen is still locked by that child.
+                        warn!(
+                            "Lock {} is held, but its supervisor {:?} is gone. Assuming an orphaned lock is active.",
+                            path.display(),
+                            pid
+                        );
+                        Ok(Acquire::Running(None))
+                    }
+                };
+            }
+            Err(errno) => return Err(io::Error::from(errno)),
+        }
+
+        if let Some(pid) = read_pid(&mut file)? {
+            warn!("Removing stale lock {} left behind by pid {}", path.display(), pid);
+        }
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        writeln!(file, "{}", getpid())?;
+        file.flush()?;
+
+        info!("Acquired instance lock {}", path.display());
+        Ok(Acquire::Acquired(InstanceLock { file, path }))
+    }
+
+    /// The pid of the supervisor holding the lock, without trying to take it.
+    pub fn running() -> io::Result<Option<Pid>> {
+        Self::running_at(&lock_path())
+    }
+
+    fn running_at(path: &Path) -> io::Result<Option<Pid>> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        match flock(file.as_raw_fd(), FlockArg::LockSharedNonblock) {
+            // Nobody holds the lock, it is released again once the file is dropped
+            Ok(()) => Ok(None),
+            Err(Errno::EWOULDBLOCK) => Ok(read_pid(&mut file)?.filter(|pid| is_alive(*pid))),
+            Err(errno) => Err(io::Error::from(errno)),
+        }
+    }
+
+    /// Clear the recorded pid. Called on a regular exit so the next start doesn't consider the
+    /// lock file stale. The `flock` itself is released by the kernel once the process exits.
+    pub fn release(&self) {
+        if let Err(err) = self.file.set_len(0) {
+            warn!("Could not clear instance lock {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+fn lock_path() -> PathBuf {
+    let file = format!("waylock-{}.lock", getuid());
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join(file),
+        None => {
+            warn!("$XDG_RUNTIME_DIR is not set, falling back to /tmp for the instance lock");
+            PathBuf::from("/tmp").join(file)
+        }
+    }
+}
+
+fn read_pid(file: &mut File) -> io::Result<Option<Pid>> {
+    let mut content = String::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_string(&mut content)?;
+    Ok(content.trim().parse().ok().map(Pid::from_raw))
+}
+
+fn is_alive(pid: Pid) -> bool {
+    // EPERM means the process exists, but belongs to someone else
+    !matches!(kill(pid, None), Err(Errno::ESRCH))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    use nix::unistd::{getpid, Pid};
+
+    use super::{Acquire, InstanceLock};
+
+    /// A lock file in a runtime directory of its own, so tests don't share locks.
+    fn lock_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("waylock-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("waylock.lock")
+    }
+
+    #[test]
+    fn a_held_lock_refuses_a_second_instance() {
+        let path = lock_file("instance-held");
+        let holder = match InstanceLock::acquire_at(path.clone()).unwrap() {
+            Acquire::Acquired(holder) => holder,
+            Acquire::Running(pid) => panic!("lock held by {:?}", pid),
+        };
+        assert_eq!(fs::read_to_string(&path).unwrap().trim(), getpid().to_string());
+        assert_eq!(InstanceLock::running_at(&path).unwrap(), Some(getpid()));
+
+        // flock conflicts between two opens of the file, even within one process
+        assert!(matches!(
+            InstanceLock::acquire_at(path.clone()).unwrap(),
+            Acquire::Running(Some(pid)) if pid == getpid()
+        ));
+
+        holder.release();
+        drop(holder);
+        assert_eq!(InstanceLock::running_at(&path).unwrap(), None);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn stale_locks_of_dead_processes_are_taken_over() {
+        let path = lock_file("instance-stale");
+        let mut child = Command::new("true").spawn().unwrap();
+        let dead = Pid::from_raw(child.id() as i32);
+        child.wait().unwrap();
+        fs::write(&path, format!("{}\n", dead)).unwrap();
+
+        assert_eq!(InstanceLock::running_at(&path).unwrap(), None);
+        assert!(matches!(InstanceLock::acquire_at(path.clone()).unwrap(), Acquire::Acquired(_)));
+        assert_eq!(fs::read_to_string(&path).unwrap().trim(), getpid().to_string());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,35 +1,120 @@
-use std::borrow::BorrowMut;
-use std::io;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::{error, fmt, io};
 use std::process::Command;
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, UNIX_EPOCH};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
 use smithay_client_toolkit::{
     reexports::{
-        calloop,
-        client::protocol::{wl_compositor, wl_shm},
+        calloop::{self, signals::Signal},
+        client::protocol::{wl_compositor, wl_output, wl_shm},
+        client::{Attached, ConnectError, Interface},
         protocols::wlr::unstable::input_inhibitor::v1::client::zwlr_input_inhibit_manager_v1,
         protocols::wlr::unstable::layer_shell::v1::client::zwlr_layer_shell_v1,
+        protocols::wlr::unstable::screencopy::v1::client::zwlr_screencopy_manager_v1,
     },
+    environment::{Environment, GlobalHandler},
     seat::keyboard::keysyms,
     WaylandSource,
 };
 
+pub use self::diagnose::diagnose;
+
 use self::auth::LockAuth;
 use self::env::LockEnv;
 use self::input::LockInput;
 use self::output::OutputHandling;
+use self::slideshow::Slideshow;
 use self::surface::LockSurface;
 use crate::config::Config;
+use crate::supervisor::{exit_code, SIGNALS};
 
 mod auth;
+mod diagnose;
 mod env;
 mod input;
 mod output;
 mod surface;
 mod canvas;
+mod background;
+mod screenshot;
+mod slideshow;
+mod animation;
+
+/// How often pending screenshots are checked for a timeout.
+const CAPTURE_POLL: Duration = Duration::from_millis(100);
+/// Time between the frames of a cross-fade, about 30 per second.
+const FADE_FRAME: Duration = Duration::from_millis(33);
+/// How long to wait before trying again to lock an output whose surface could not be created.
+const SURFACE_RETRY: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum LockError {
+    /// A protocol required by waylock is not advertised by the compositor.
+    MissingGlobal(&'static str),
+    Connection(ConnectError),
+    Shm(io::Error),
+    Auth(String),
+    Io(io::Error),
+}
+
+impl LockError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::MissingGlobal(_) => exit_code::MISSING_GLOBAL,
+            Self::Connection(_) => exit_code::CONNECTION_FAILED,
+            Self::Shm(_) => exit_code::SHM_FAILED,
+            Self::Auth(_) => exit_code::AUTH_FAILED,
+            Self::Io(err) => match err.kind() {
+                io::ErrorKind::BrokenPipe
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted => exit_code::COMPOSITOR_GONE,
+                _ => exit_code::CRASH,
+            },
+        }
+    }
+}
+
+impl From<io::Error> for LockError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
 
-#[derive(Copy, Clone, PartialEq)]
+impl<S> From<calloop::InsertError<S>> for LockError {
+    fn from(err: calloop::InsertError<S>) -> Self {
+        Self::Io(err.error)
+    }
+}
+
+impl error::Error for LockError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::MissingGlobal(_) | Self::Auth(_) => None,
+            Self::Connection(err) => Some(err),
+            Self::Shm(err) | Self::Io(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingGlobal(name) => {
+                write!(f, "The compositor does not support the required protocol {}.", name)
+            }
+            Self::Connection(err) => write!(f, "Failed to connect to a wayland server: {}", err),
+            Self::Shm(err) => write!(f, "Failed to create shared memory for drawing: {}", err),
+            Self::Auth(msg) => write!(f, "Failed to initialize authentication: {}", msg),
+            Self::Io(err) => write!(f, "I/O error while locking: {}", err),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum LockState {
     Init,
     Input,
@@ -46,41 +131,118 @@ impl LockState {
     }
 }
 
-pub fn lock_screen(config: Arc<Config>) -> io::Result<()> {
+/// Lock the screen until the user authenticates. In safe mode only the state color is drawn.
+/// `on_locked` is called once the first output is covered.
+pub fn lock_screen(
+    config: Arc<Config>,
+    safe_mode: bool,
+    on_locked: impl FnOnce(),
+) -> Result<(), LockError> {
+    let mut on_locked = Some(on_locked);
+
+    // Fail before locking anything if the user could never unlock again
+    let lock_auth = LockAuth::new()?;
+
+    // Shared with the output listener, so new surfaces pick up a reloaded configuration
+    let config = Rc::new(RefCell::new(config));
     let (lock_env, display, queue) = LockEnv::init_environment()?;
 
-    let _inhibitor = lock_env
-        .require_global::<zwlr_input_inhibit_manager_v1::ZwlrInputInhibitManagerV1>()
-        .get_inhibitor();
+    let _inhibitor =
+        get_global::<zwlr_input_inhibit_manager_v1::ZwlrInputInhibitManagerV1>(&lock_env)?
+            .get_inhibitor();
+
+    // Errors can't be returned from the output listener, so they are stored until the next loop
+    let surface_error = Rc::new(Cell::new(None));
+    // Outputs that appeared once the screen was locked, but could not be locked themselves
+    let surface_retries = Rc::new(RefCell::new(Vec::new()));
 
-    let lock_surfaces = {
-        let compositor = lock_env.require_global::<wl_compositor::WlCompositor>();
-        let layer_shell = lock_env.require_global::<zwlr_layer_shell_v1::ZwlrLayerShellV1>();
-        let shm = lock_env.require_global::<wl_shm::WlShm>();
+    // Screenshots being taken of outputs that are not locked yet
+    let captures = Rc::new(RefCell::new(Vec::new()));
 
-        let lock_surfaces = Arc::new(Mutex::new(Vec::new()));
+    // Images of a background directory are decoded on other threads and sent back to the loop
+    let (slide_sender, slide_channel) = calloop::channel::channel();
+    let slideshow = Rc::new(RefCell::new(Slideshow::new(slide_sender)));
+    slideshow.borrow_mut().set_images(config.borrow().background.slideshow.clone());
+
+    let (lock_surfaces, create_surface) = {
+        let compositor = get_global::<wl_compositor::WlCompositor>(&lock_env)?;
+        let layer_shell = get_global::<zwlr_layer_shell_v1::ZwlrLayerShellV1>(&lock_env)?;
+        let shm = get_global::<wl_shm::WlShm>(&lock_env)?;
+        let screencopy =
+            lock_env.get_global::<zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1>();
+
+        let lock_surfaces = Rc::new(RefCell::new(Vec::new()));
 
         let lock_surfaces_handle = lock_surfaces.clone();
-        let config2 = config.clone();
-        lock_env.set_output_created_listener(Some(move |id, output| {
-            lock_surfaces_handle.lock().unwrap().borrow_mut().push((
-                id,
-                LockSurface::new(
-                    &output,
-                    &compositor.clone(),
-                    &layer_shell.clone(),
-                    shm.clone(),
-                    config2.clone(),
-                ),
-            ));
+        let config_handle = config.clone();
+        let surface_error_handle = surface_error.clone();
+        let surface_retries_handle = surface_retries.clone();
+        let shm_handle = shm.clone();
+        let slideshow_handle = slideshow.clone();
+        let create_surface = Rc::new(move |id, output: &_, screenshot: Option<_>| {
+            // A screenshot is shown instead of the slideshow
+            if screenshot.is_none() {
+                slideshow_handle.borrow_mut().next(id);
+            }
+            match LockSurface::new(
+                output,
+                &compositor.clone(),
+                &layer_shell.clone(),
+                shm_handle.clone(),
+                config_handle.borrow().clone(),
+                safe_mode,
+                screenshot,
+            ) {
+                Ok(lock_surface) => lock_surfaces_handle.borrow_mut().push((id, lock_surface)),
+                // Giving up once the screen is locked would unlock it, e.g. when memory runs short
+                // as an output is plugged in
+                Err(err) if lock_surfaces_handle.borrow().iter().any(|(_, s)| s.is_mapped()) => {
+                    log::error!("Output {}: could not lock it, trying again: {}", id, err);
+                    surface_retries_handle.borrow_mut().push((id, output.clone(), Instant::now()));
+                }
+                Err(err) => surface_error_handle.set(Some(err)),
+            }
+        });
+
+        let config_handle = config.clone();
+        let create_surface_handle = create_surface.clone();
+        let captures_handle = captures.clone();
+        // Outputs plugged in later would show the desktop until captured, and the capture of it
+        let initial = Rc::new(Cell::new(true));
+        let initial_handle = initial.clone();
+        lock_env.set_output_created_listener(Some(move |id, output: wl_output::WlOutput| {
+            let screenshot = initial_handle.get() && {
+                let config = config_handle.borrow();
+                config.background.screenshot && !(safe_mode || config.safe_mode)
+            };
+            match &screencopy {
+                // The output is locked once its screenshot is taken, or the capture times out
+                Some(screencopy) if screenshot => {
+                    let create_surface = create_surface_handle.clone();
+                    let capture = screenshot::capture(screencopy, shm.clone(), &output, {
+                        let output = output.clone();
+                        move |screenshot| create_surface(id, &output, screenshot)
+                    });
+                    captures_handle.borrow_mut().push((id, capture));
+                }
+                _ => create_surface_handle(id, &output, None),
+            }
         }));
+        // The listener was called for every output present at the time
+        initial.set(false);
 
         let lock_surfaces_handle = lock_surfaces.clone();
+        let captures_handle = captures.clone();
+        let slideshow_handle = slideshow.clone();
+        let surface_retries_handle = surface_retries.clone();
         lock_env.set_output_removed_listener(Some(move |id| {
-            lock_surfaces_handle.lock().unwrap().borrow_mut().retain(|(i, _)| *i != id);
+            lock_surfaces_handle.borrow_mut().retain(|(i, _)| *i != id);
+            captures_handle.borrow_mut().retain(|(i, _)| *i != id);
+            surface_retries_handle.borrow_mut().retain(|(i, _, _)| *i != id);
+            slideshow_handle.borrow_mut().remove(id);
         }));
 
-        lock_surfaces
+        (lock_surfaces, create_surface)
     };
 
     let mut event_loop = calloop::EventLoop::new()?;
@@ -89,27 +251,35 @@ pub fn lock_screen(config: Arc<Config>) -> io::Result<()> {
 
     WaylandSource::new(queue).quick_insert(event_loop.handle())?;
 
-    let lock_auth = LockAuth::new();
+    let pending_signals = Rc::new(RefCell::new(VecDeque::new()));
+    let pending_signals_handle = pending_signals.clone();
+    event_loop.handle().insert_source(
+        calloop::signals::Signals::new(SIGNALS)?,
+        move |event, _, _| pending_signals_handle.borrow_mut().push_back(event.signal()),
+    )?;
+
     let mut current_password = String::new();
 
     let mut lock_state = LockState::Init;
+    let mut failed_attempts = 0;
 
     let set_state = |state, num| {
-        for (_, lock_surface) in lock_surfaces.lock().unwrap().borrow_mut().iter_mut() {
+        for (_, lock_surface) in lock_surfaces.borrow_mut().iter_mut() {
             lock_surface.set_state(state);
             lock_surface.chars_entered(num);
+            lock_surface.resume_animation();
             lock_surface.set_redraw();
         }
     };
 
-    let timer = calloop::timer::Timer::new().unwrap();
+    let timer = calloop::timer::Timer::new()?;
     let timer_handle = timer.handle();
     timer_handle
-        .add_timeout(Duration::from_secs(60 - UNIX_EPOCH.elapsed().unwrap().as_secs() % 60), ());
+        .add_timeout(Duration::from_secs(60 - UNIX_EPOCH.elapsed().unwrap_or_default().as_secs() % 60), ());
 
     let surface_ref = lock_surfaces.clone();
     event_loop.handle().insert_source(timer, move |_event, metadata, _shared_data| {
-        for (_, lock_surface) in surface_ref.lock().unwrap().borrow_mut().iter_mut() {
+        for (_, lock_surface) in surface_ref.borrow_mut().iter_mut() {
             lock_surface.set_redraw();
             lock_surface.handle_events();
         }
@@ -117,21 +287,141 @@ pub fn lock_screen(config: Arc<Config>) -> io::Result<()> {
         metadata.add_timeout(Duration::from_secs(60), ());
     })?;
 
+    let surface_ref = lock_surfaces.clone();
+    event_loop.handle().insert_source(slide_channel, move |event, _, _| {
+        if let calloop::channel::Event::Msg((id, image)) = event {
+            // The output may have been removed while its image was decoded
+            let mut lock_surfaces = surface_ref.borrow_mut();
+            if let Some((_, lock_surface)) = lock_surfaces.iter_mut().find(|(i, _)| *i == id) {
+                lock_surface.set_slide(image);
+            }
+        }
+    })?;
+
+    let slide_timer = calloop::timer::Timer::new()?;
+    let slide_timer_handle = slide_timer.handle();
+    if let Some(interval) = config.borrow().background.interval {
+        slide_timer_handle.add_timeout(interval, ());
+    }
+    let surface_ref = lock_surfaces.clone();
+    let (config_handle, slideshow_handle) = (config.clone(), slideshow.clone());
+    event_loop.handle().insert_source(slide_timer, move |_event, metadata, _shared_data| {
+        for (id, lock_surface) in surface_ref.borrow().iter() {
+            if !lock_surface.shows_screenshot() {
+                slideshow_handle.borrow_mut().next(*id);
+            }
+        }
+        if let Some(interval) = config_handle.borrow().background.interval {
+            metadata.add_timeout(interval, ());
+        }
+    })?;
+
+    // The configuration is reloaded on another thread and sent back to the loop
+    let (reload_sender, reload_channel) =
+        calloop::channel::channel::<(u64, Result<Config, String>)>();
+    // Only the latest of several reloads in a row is applied
+    let reloads = Rc::new(Cell::new(0));
+    let (surface_ref, reloads_handle) = (lock_surfaces.clone(), reloads.clone());
+    let (config_handle, slideshow_handle) = (config.clone(), slideshow.clone());
+    event_loop.handle().insert_source(reload_channel, move |event, _, _| match event {
+        calloop::channel::Event::Msg((reload, _)) if reload != reloads_handle.get() => {}
+        calloop::channel::Event::Msg((_, Ok(new_config))) => {
+            log::info!("Reloaded the configuration");
+            let new_config = Arc::new(new_config);
+            // Swap the configuration of every surface at once, before any redraw
+            for (_, lock_surface) in surface_ref.borrow_mut().iter_mut() {
+                lock_surface.set_config(new_config.clone());
+                lock_surface.set_reload_error(None);
+                lock_surface.set_redraw();
+            }
+            // Start over only if the directory of images changed
+            let images = new_config.background.slideshow.clone();
+            if slideshow_handle.borrow_mut().set_images(images) {
+                for (id, lock_surface) in surface_ref.borrow().iter() {
+                    if !lock_surface.shows_screenshot() {
+                        slideshow_handle.borrow_mut().next(*id);
+                    }
+                }
+            }
+            slide_timer_handle.cancel_all_timeouts();
+            if let Some(interval) = new_config.background.interval {
+                slide_timer_handle.add_timeout(interval, ());
+            }
+            config_handle.replace(new_config);
+        }
+        calloop::channel::Event::Msg((_, Err(err))) => {
+            log::warn!("Keeping the old configuration: {}", err);
+            for (_, lock_surface) in surface_ref.borrow_mut().iter_mut() {
+                lock_surface.set_reload_error(Some(err.clone()));
+                lock_surface.set_redraw();
+            }
+        }
+        calloop::channel::Event::Closed => {}
+    })?;
+
     loop {
+        if let Some(err) = surface_error.take() {
+            return Err(err);
+        }
+
+        // Handle all signals received since last check
+        while let Some(signal) = pending_signals.borrow_mut().pop_front() {
+            match signal {
+                Signal::SIGTERM | Signal::SIGINT if config.borrow().unlock_on_term => {
+                    log::info!("Unlocking on {:?}", signal);
+                    return Ok(());
+                }
+                Signal::SIGUSR1 if config.borrow().unlock_on_usr1 => {
+                    log::info!("Unlocking on {:?}", signal);
+                    return Ok(());
+                }
+                Signal::SIGHUP => {
+                    // Loading fonts and images takes a while, the lock has to stay responsive
+                    let reload_sender = reload_sender.clone();
+                    reloads.set(reloads.get() + 1);
+                    let reload = reloads.get();
+                    thread::spawn(move || {
+                        let config = Config::new().map_err(|err| err.to_string());
+                        // Fails only if the screen was unlocked in the meantime
+                        let _ = reload_sender.send((reload, config));
+                    });
+                }
+                Signal::SIGUSR2 => {
+                    log::info!(
+                        "State: {:?}, {} characters entered",
+                        lock_state,
+                        current_password.chars().count()
+                    );
+                    for (id, lock_surface) in lock_surfaces.borrow().iter() {
+                        let (width, height) = lock_surface.dimensions();
+                        log::info!("Output {}: surface of {}x{}", id, width, height);
+                    }
+                }
+                _ => log::warn!("Ignoring {:?}, unlocking through it is disabled", signal),
+            }
+        }
+
         // Handle all input received since last check
         while let Some((keysym, utf8)) = lock_input.pop() {
             match keysym {
                 keysyms::XKB_KEY_KP_Enter | keysyms::XKB_KEY_Return => {
+                    if current_password.is_empty() && config.borrow().ignore_empty_password {
+                        continue;
+                    }
                     match lock_auth.check_password(&current_password) {
                         Ok(()) => return Ok(()),
                         Err(err) => {
                             log::warn!("Authentication failure {}", err);
+                            failed_attempts += 1;
+                            for (_, lock_surface) in lock_surfaces.borrow_mut().iter_mut() {
+                                lock_surface.set_failed_attempts(failed_attempts);
+                            }
                             lock_state = LockState::Fail;
                             set_state(lock_state, 0);
                             current_password = String::new();
 
-                            if let Some(command) = &config.fail_command {
-                                if let Err(err) = Command::new("sh").arg("-c").arg(command.to_owned()).spawn() {
+                            if let Some(command) = &config.borrow().fail_command {
+                                if let Err(err) = Command::new("sh").arg("-c").arg(command).spawn() {
                                     log::warn!("Error executing fail command \"{}\": {}", command, err);
                                 }
                             }
@@ -163,10 +453,19 @@ pub fn lock_screen(config: Arc<Config>) -> io::Result<()> {
             }
         }
 
+        // Cross-fades are drawn frame by frame until they end
+        let mut fading = false;
+        for (_, lock_surface) in lock_surfaces.borrow_mut().iter_mut() {
+            if lock_surface.fading() {
+                lock_surface.set_redraw();
+                fading = true;
+            }
+        }
+
         // This is ugly, let's hope that some version of drain_filter() gets stabilized soon
         // https://github.com/rust-lang/rust/issues/43244
         {
-            let mut lock_surfaces = lock_surfaces.lock().unwrap();
+            let mut lock_surfaces = lock_surfaces.borrow_mut();
             let mut i = 0;
             while i != lock_surfaces.len() {
                 if lock_surfaces[i].1.handle_events() {
@@ -176,12 +475,74 @@ pub fn lock_screen(config: Arc<Config>) -> io::Result<()> {
                 }
             }
         }
+        if lock_surfaces.borrow().iter().any(|(_, lock_surface)| lock_surface.is_mapped()) {
+            if let Some(on_locked) = on_locked.take() {
+                on_locked();
+            }
+        }
+
+        // Lock outputs without their screenshot rather than leaving them unlocked any longer
+        let expired: Vec<_> = {
+            let mut captures = captures.borrow_mut();
+            captures.retain(|(_, capture)| capture.pending());
+            let (expired, pending) =
+                captures.drain(..).partition(|(_, capture)| capture.expired());
+            *captures = pending;
+            expired
+        };
+        for (id, capture) in expired {
+            log::warn!("Output {}: gave up waiting for a screenshot", id);
+            capture.cancel();
+        }
+        // Try again to lock outputs whose surface could not be created
+        let due: Vec<_> = {
+            let mut retries = surface_retries.borrow_mut();
+            let (due, waiting) =
+                retries.drain(..).partition(|(_, _, failed)| failed.elapsed() >= SURFACE_RETRY);
+            *retries = waiting;
+            due
+        };
+        for (id, output, _) in due {
+            create_surface(id, &output, None);
+        }
+
+        let timeout = match (fading, captures.borrow().is_empty()) {
+            (true, _) => Some(FADE_FRAME),
+            (false, false) => Some(CAPTURE_POLL),
+            (false, true) => None,
+        };
+        // Wake up for the next frame of animated backgrounds
+        let next_frame = lock_surfaces
+            .borrow()
+            .iter()
+            .filter_map(|(_, lock_surface)| lock_surface.until_next_frame())
+            .min();
+        let next_retry = surface_retries
+            .borrow()
+            .iter()
+            .map(|(_, _, failed)| SURFACE_RETRY.saturating_sub(failed.elapsed()))
+            .min();
+        // And to clear the highlight of the last keypress
+        let next_highlight = lock_surfaces
+            .borrow()
+            .iter()
+            .filter_map(|(_, lock_surface)| lock_surface.until_highlight_ends())
+            .min();
+        let timeout =
+            [timeout, next_frame, next_retry, next_highlight].iter().flatten().min().copied();
 
         retry_on_interrupt(|| display.flush())?;
-        retry_on_interrupt(|| event_loop.dispatch(None, &mut ()))?;
+        retry_on_interrupt(|| event_loop.dispatch(timeout, &mut ()))?;
     }
 }
 
+fn get_global<I: Interface>(lock_env: &Environment<LockEnv>) -> Result<Attached<I>, LockError>
+where
+    LockEnv: GlobalHandler<I>,
+{
+    lock_env.get_global::<I>().ok_or(LockError::MissingGlobal(I::NAME))
+}
+
 fn retry_on_interrupt<T, F: FnMut() -> io::Result<T>>(mut f: F) -> io::Result<T> {
     loop {
         match f() {
@@ -191,3 +552,29 @@ fn retry_on_interrupt<T, F: FnMut() -> io::Result<T>>(mut f: F) -> io::Result<T>
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_errors_map_to_exit_codes() {
+        let codes = [
+            (LockError::MissingGlobal("zwlr_layer_shell_v1"), exit_code::MISSING_GLOBAL),
+            (LockError::Shm(io::ErrorKind::OutOfMemory.into()), exit_code::SHM_FAILED),
+            (LockError::Auth("no pam".into()), exit_code::AUTH_FAILED),
+            (LockError::Io(io::ErrorKind::BrokenPipe.into()), exit_code::COMPOSITOR_GONE),
+            (LockError::Io(io::ErrorKind::ConnectionReset.into()), exit_code::COMPOSITOR_GONE),
+            (LockError::Io(io::ErrorKind::Other.into()), exit_code::CRASH),
+        ];
+        for (err, code) in &codes {
+            assert_eq!(err.exit_code(), *code, "{:?}", err);
+        }
+
+        // Until the screen is locked, only a gone compositor ends the lock
+        for (err, code) in &codes {
+            let fatal = *code == exit_code::COMPOSITOR_GONE;
+            assert_eq!(exit_code::is_fatal(*code), fatal, "{:?}", err);
+        }
+    }
+}
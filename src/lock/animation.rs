@@ -0,0 +1,107 @@
+use std::time::{Duration, Instant};
+
+/// Which frame of an animated background is shown, and when the next one is due.
+pub struct Animation {
+    delays: Vec<Duration>,
+    frame: usize,
+    /// When the current frame was due, which may be before it was drawn
+    shown: Instant,
+    /// When the animation last started playing
+    started: Instant,
+    pause_after: Option<Duration>,
+}
+
+impl Animation {
+    pub fn new(delays: Vec<Duration>, pause_after: Option<Duration>, now: Instant) -> Self {
+        Animation { delays, frame: 0, shown: now, started: now, pause_after }
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Whether the animation played for `pause_after` and stopped.
+    pub fn paused(&self, now: Instant) -> bool {
+        self.pause_after.is_some_and(|pause_after| now.duration_since(self.started) >= pause_after)
+    }
+
+    /// Play a paused animation again from the frame it stopped at.
+    pub fn resume(&mut self, now: Instant) {
+        if self.paused(now) {
+            self.started = now;
+            self.shown = now;
+        }
+    }
+
+    /// Move on to the frame due at `now`. Frames that were due while nothing was drawn, e.g. as
+    /// the output was off, are skipped rather than played faster. Returns whether it changed.
+    pub fn advance(&mut self, now: Instant) -> bool {
+        if self.paused(now) || now.duration_since(self.shown) < self.delays[self.frame] {
+            return false;
+        }
+        let total: Duration = self.delays.iter().sum();
+        let mut late = now.duration_since(self.shown);
+        if late >= total {
+            late = Duration::from_nanos((late.as_nanos() % total.as_nanos()) as u64);
+        }
+        while late >= self.delays[self.frame] {
+            late -= self.delays[self.frame];
+            self.frame = (self.frame + 1) % self.delays.len();
+        }
+        self.shown = now - late;
+        true
+    }
+
+    /// How long until the next frame is due, `None` while paused.
+    pub fn until_next(&self, now: Instant) -> Option<Duration> {
+        if self.paused(now) {
+            return None;
+        }
+        Some((self.shown + self.delays[self.frame]).saturating_duration_since(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::Animation;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn frames_follow_their_delays() {
+        let start = Instant::now();
+        let mut animation = Animation::new(vec![ms(100), ms(200), ms(50)], None, start);
+        assert!(!animation.advance(start + ms(99)));
+        assert_eq!(animation.until_next(start + ms(40)), Some(ms(60)));
+        assert!(animation.advance(start + ms(110)));
+        assert_eq!(animation.frame(), 1);
+        assert_eq!(animation.until_next(start + ms(110)), Some(ms(190)));
+
+        // Late frames are skipped, and the whole loop is never played more than once
+        assert!(animation.advance(start + ms(350) + ms(350) * 10));
+        assert_eq!(animation.frame(), 0);
+        assert!(animation.advance(start + ms(3950) + ms(120)));
+        assert_eq!(animation.frame(), 1);
+    }
+
+    #[test]
+    fn animations_pause_until_resumed() {
+        let start = Instant::now();
+        let mut animation = Animation::new(vec![ms(100), ms(100)], Some(ms(1000)), start);
+        assert!(animation.advance(start + ms(950)));
+        assert_eq!(animation.frame(), 1);
+        assert!(animation.paused(start + ms(1000)));
+        assert!(!animation.advance(start + ms(1500)));
+        assert_eq!(animation.until_next(start + ms(1500)), None);
+
+        animation.resume(start + ms(2000));
+        assert!(!animation.paused(start + ms(2000)));
+        assert_eq!(animation.until_next(start + ms(2000)), Some(ms(100)));
+        assert!(animation.advance(start + ms(2100)));
+        assert_eq!(animation.frame(), 0);
+    }
+}
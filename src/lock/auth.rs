@@ -1,34 +1,32 @@
 use pam::{Authenticator, PamError};
 use users::get_current_username;
 
+use super::LockError;
+
+/// PAM service used to authenticate the user.
+pub const PAM_SERVICE: &str = "system-auth";
+
 pub struct LockAuth {
     login: String,
 }
 
 impl LockAuth {
-    pub fn new() -> Self {
+    pub fn new() -> Result<Self, LockError> {
         let login = get_current_username()
-            .unwrap_or_else(|| {
-                log::error!("Failed to get current username.");
-                panic!();
-            })
+            .ok_or_else(|| LockError::Auth("Failed to get current username.".to_owned()))?
             .into_string()
-            .unwrap_or_else(|_| {
-                log::error!("Failed to parse the current username.");
-                panic!();
-            });
-        Self { login }
+            .map_err(|_| LockError::Auth("Failed to parse the current username.".to_owned()))?;
+
+        // Make sure PAM is usable before locking, the handle itself is created per attempt
+        Authenticator::with_password(PAM_SERVICE)
+            .map_err(|err| LockError::Auth(format!("Failed to initialize PAM client: {}", err)))?;
+
+        Ok(Self { login })
     }
 
     /// Attempt to authenticate with PAM. Returns true on success, otherwise false.
     pub fn check_password(&self, password: &str) -> Result<(), PamError> {
-        let mut authenticator = match Authenticator::with_password("system-auth") {
-            Ok(authenticator) => authenticator,
-            Err(err) => {
-                log::error!("Failed to initialize PAM client: {}", err);
-                panic!();
-            }
-        };
+        let mut authenticator = Authenticator::with_password(PAM_SERVICE)?;
         authenticator.get_handler().set_credentials(&self.login, password);
         authenticator.authenticate()
     }
@@ -0,0 +1,206 @@
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+
+use crate::config::{Background, ScaleMode};
+use crate::lock::canvas::Canvas;
+
+/// Render the background for an output of the given dimensions: `color`, the gradient and the
+/// pattern, `image` scaled by its mode, then the effects in order. The result is opaque and meant
+/// to be cached, as scaling is too slow to do for every frame.
+pub fn render(
+    background: &Background,
+    image: Option<(&RgbaImage, ScaleMode)>,
+    color: u32,
+    dimensions: (usize, usize),
+) -> Vec<u32> {
+    let mut frame = vec![0; dimensions.0 * dimensions.1];
+    let canvas = Canvas { mem: frame.as_mut_ptr() as *mut u8, dimensions, color, fonts: &[] };
+    canvas.fill();
+    if let Some(gradient) = &background.gradient {
+        canvas.gradient(gradient, background.dither);
+    }
+    if let Some(pattern) = &background.pattern {
+        canvas.pattern(pattern);
+    }
+
+    if let Some((image, mode)) = image {
+        let image_dimensions = (image.width() as usize, image.height() as usize);
+        let (size, position) = placement(mode, image_dimensions, dimensions);
+        if size.0 > 0 && size.1 > 0 && mode == ScaleMode::Fill {
+            // Scaling all of an image with an extreme aspect ratio could take gigabytes
+            let (x, y, width, height) = visible(image_dimensions, dimensions);
+            let image = imageops::crop_imm(image, x as u32, y as u32, width as u32, height as u32);
+            draw(&canvas, &image.to_image(), mode, dimensions, (0, 0));
+        } else if size.0 > 0 && size.1 > 0 {
+            draw(&canvas, image, mode, size, position);
+        }
+    }
+    for effect in &background.effects {
+        canvas.apply(*effect);
+    }
+    frame
+}
+
+/// Draw `image` scaled to `size` at `position`, or tiled from the top left corner.
+fn draw(
+    canvas: &Canvas,
+    image: &RgbaImage,
+    mode: ScaleMode,
+    size: (usize, usize),
+    position: (isize, isize),
+) {
+    let image_dimensions = (image.width() as usize, image.height() as usize);
+
+    let scaled;
+    let image = if size == image_dimensions {
+        image
+    } else {
+        scaled = imageops::resize(image, size.0 as u32, size.1 as u32, FilterType::Triangle);
+        &scaled
+    };
+    let pixels: Vec<u32> = image
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b, a] = pixel.0;
+            u32::from_be_bytes([a, r, g, b])
+        })
+        .collect();
+
+    if mode == ScaleMode::Tile {
+        for y in (0..canvas.dimensions.1).step_by(size.1) {
+            for x in (0..canvas.dimensions.0).step_by(size.0) {
+                canvas.blit(&pixels, size, (x as isize, y as isize));
+            }
+        }
+    } else {
+        canvas.blit(&pixels, size, position);
+    }
+}
+
+/// The size an image is scaled to and the position of its top left corner on an output.
+fn placement(
+    mode: ScaleMode,
+    image: (usize, usize),
+    output: (usize, usize),
+) -> ((usize, usize), (isize, isize)) {
+    let ((image_width, image_height), (width, height)) = (image, output);
+    if image_width == 0 || image_height == 0 {
+        return ((0, 0), (0, 0));
+    }
+
+    // Whether the output is wider than the image, relative to their heights
+    let wider = width * image_height >= height * image_width;
+    let scale_to_width = (width, (image_height * width + image_width / 2) / image_width);
+    let scale_to_height = ((image_width * height + image_height / 2) / image_height, height);
+
+    let size = match mode {
+        ScaleMode::Fill if wider => scale_to_width,
+        ScaleMode::Fill => scale_to_height,
+        ScaleMode::Fit if wider => scale_to_height,
+        ScaleMode::Fit => scale_to_width,
+        ScaleMode::Center | ScaleMode::Tile => image,
+        ScaleMode::Stretch => output,
+    };
+    let position = match mode {
+        ScaleMode::Tile | ScaleMode::Stretch => (0, 0),
+        _ => ((width as isize - size.0 as isize) / 2, (height as isize - size.1 as isize) / 2),
+    };
+    (size, position)
+}
+
+/// The part of an image that is visible when it fills an output, as its left, top, width and
+/// height.
+fn visible(image: (usize, usize), output: (usize, usize)) -> (usize, usize, usize, usize) {
+    let ((image_width, image_height), (width, height)) = (image, output);
+    if width * image_height >= height * image_width {
+        let visible_height = ((height * image_width + width / 2) / width).clamp(1, image_height);
+        (0, (image_height - visible_height) / 2, image_width, visible_height)
+    } else {
+        let visible_width = ((width * image_height + height / 2) / height).clamp(1, image_width);
+        ((image_width - visible_width) / 2, 0, visible_width, image_height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use image::{Rgba, RgbaImage};
+
+    use super::{placement, visible};
+    use crate::config::{Background, Effect, Pattern, PatternKind, ScaleMode};
+
+    fn plain() -> Background {
+        Background {
+            image: None,
+            frames: Vec::new(),
+            pause_after: None,
+            slideshow: Vec::new(),
+            interval: None,
+            fade: Duration::from_secs(1),
+            mode: ScaleMode::Fill,
+            screenshot: false,
+            effects: Vec::new(),
+            gradient: None,
+            dither: false,
+            pattern: None,
+        }
+    }
+
+    #[test]
+    fn placements() {
+        let output = (1920, 1080);
+        assert_eq!(placement(ScaleMode::Fill, (1000, 1000), output), ((1920, 1920), (0, -420)));
+        assert_eq!(placement(ScaleMode::Fill, (3840, 2160), output), ((1920, 1080), (0, 0)));
+        assert_eq!(placement(ScaleMode::Fit, (1000, 1000), output), ((1080, 1080), (420, 0)));
+        assert_eq!(placement(ScaleMode::Fit, (4000, 1000), output), ((1920, 480), (0, 300)));
+        assert_eq!(placement(ScaleMode::Center, (2000, 100), output), ((2000, 100), (-40, 490)));
+        assert_eq!(placement(ScaleMode::Tile, (64, 64), output), ((64, 64), (0, 0)));
+        assert_eq!(placement(ScaleMode::Stretch, (64, 64), output), ((1920, 1080), (0, 0)));
+    }
+
+    #[test]
+    fn fill_scales_only_the_visible_part() {
+        assert_eq!(visible((1000, 1000), (1920, 1080)), (0, 218, 1000, 563));
+        assert_eq!(visible((4000, 1000), (1920, 1080)), (1111, 0, 1778, 1000));
+        assert_eq!(visible((1, 10000), (1920, 1080)), (0, 4999, 1, 1));
+
+        let mut image = RgbaImage::from_pixel(1, 10000, Rgba([0xff, 0, 0, 0xff]));
+        image.put_pixel(0, 5000, Rgba([0, 0, 0xff, 0xff]));
+        let frame = super::render(&plain(), Some((&image, ScaleMode::Fill)), 0, (1920, 1080));
+        assert!(frame.iter().all(|pixel| *pixel == 0xffff0000));
+    }
+
+    #[test]
+    fn fit_shows_the_color_around_the_image() {
+        let image = RgbaImage::from_pixel(2, 2, Rgba([0xff, 0, 0, 0xff]));
+        let frame = super::render(&plain(), Some((&image, ScaleMode::Fit)), 0xff0000ff, (4, 2));
+        assert_eq!(frame[..4], [0xff0000ff, 0xffff0000, 0xffff0000, 0xff0000ff]);
+    }
+
+    #[test]
+    fn tiles_repeat() {
+        let mut image = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 0xff]));
+        image.put_pixel(0, 0, Rgba([0xff, 0xff, 0xff, 0xff]));
+        let frame = super::render(&plain(), Some((&image, ScaleMode::Tile)), 0xff0000ff, (5, 1));
+        assert_eq!(frame, vec![0xffffffff, 0xff000000, 0xffffffff, 0xff000000, 0xffffffff]);
+    }
+
+    #[test]
+    fn effects_apply_to_the_color_without_an_image() {
+        let effects = vec![Effect::Darken(0.5), Effect::Tint(0x800000ff)];
+        let background = Background { effects, ..plain() };
+        let frame = super::render(&background, None, 0xffff0000, (2, 1));
+        assert_eq!(frame, vec![0xff3f0080, 0xff3f0080]);
+    }
+
+    #[test]
+    fn images_cover_the_pattern() {
+        let pattern = Pattern { kind: PatternKind::Checker, size: 1, color: 0xffffffff };
+        let background = Background { pattern: Some(pattern), ..plain() };
+        let image = RgbaImage::from_pixel(1, 1, Rgba([0xff, 0, 0, 0xff]));
+        let frame =
+            super::render(&background, Some((&image, ScaleMode::Center)), 0xff000000, (3, 1));
+        assert_eq!(frame, vec![0xff000000, 0xffff0000, 0xff000000]);
+    }
+}
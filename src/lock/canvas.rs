@@ -1,6 +1,10 @@
-use fontdue::layout::Layout;
+use std::f64::consts::TAU;
+
+use fontdue::layout::{Layout, TextStyle};
 use fontdue::Font;
 
+use crate::config::{Effect, Gradient, GradientKind, Pattern, PatternKind};
+
 pub struct Canvas<'a> {
     pub mem: *mut u8,
     pub dimensions: (usize, usize),
@@ -9,53 +13,316 @@ pub struct Canvas<'a> {
 }
 
 impl Canvas<'_> {
+    /// Fill the whole canvas. It is the bottom layer, so a translucent color is blended over
+    /// black to keep the surface opaque.
     pub fn fill(&self) {
         let size = self.dimensions.0 * self.dimensions.1;
-        let buf = unsafe { std::slice::from_raw_parts_mut(self.mem as *mut u32, size as usize) };
-        buf.fill(self.color);
+        let buf = unsafe { std::slice::from_raw_parts_mut(self.mem as *mut u32, size) };
+        buf.fill(blend(0xff000000, self.color, 0xff));
     }
 
+    /// Fill the rectangle from `from` up to `to`, without anti-aliasing. It is clipped to the
+    /// canvas.
     pub fn draw_square(&self, from: (usize, usize), to: (usize, usize)) {
-        let (from_x, from_y) = from;
-        let (to_x, to_y) = to;
+        let (to_x, to_y) = (to.0.min(self.dimensions.0), to.1.min(self.dimensions.1));
+        let (from_x, from_y) = (from.0.min(to_x), from.1.min(to_y));
 
         let size = self.dimensions.0 * self.dimensions.1;
-        let buf = unsafe { std::slice::from_raw_parts_mut(self.mem as *mut u32, size as usize) };
+        let buf = unsafe { std::slice::from_raw_parts_mut(self.mem as *mut u32, size) };
 
         for y in from_y..to_y {
             let y_off = y * self.dimensions.0;
-            for x in (y_off + from_x)..(y_off + to_x) {
-                buf[x as usize] = self.color
+            let row = &mut buf[(y_off + from_x)..(y_off + to_x)];
+            if self.color >> 24 == 0xff {
+                row.fill(self.color);
+            } else {
+                row.iter_mut().for_each(|pixel| *pixel = blend(*pixel, self.color, 0xff));
             }
         }
     }
 
-    fn draw_bitmap(&self, bitmap: &[u8], dimensions: (usize, usize), position: (usize, usize)) {
+    /// Copy the ARGB pixels `src` of the given dimensions to `position`, which may be partly or
+    /// entirely outside of the canvas. Translucent pixels are blended over the canvas.
+    pub fn blit(&self, src: &[u32], dimensions: (usize, usize), position: (isize, isize)) {
+        let (width, height) = (self.dimensions.0 as isize, self.dimensions.1 as isize);
+        let (src_width, src_height) = (dimensions.0 as isize, dimensions.1 as isize);
         let (x_pos, y_pos) = position;
-        let (x_dim, y_dim) = dimensions;
+
+        let (from_x, to_x) = (x_pos.max(0), (x_pos + src_width).min(width));
+        let (from_y, to_y) = (y_pos.max(0), (y_pos + src_height).min(height));
+        if from_x >= to_x || from_y >= to_y {
+            return;
+        }
 
         let size = self.dimensions.0 * self.dimensions.1;
-        let buf = unsafe { std::slice::from_raw_parts_mut(self.mem as *mut u32, size as usize) };
-        let mut src = bitmap.iter();
-
-        for i in 0..y_dim {
-            let mut buf_offset = ((y_pos + i) * self.dimensions.0 + x_pos) as usize;
-            for _ in 0..x_dim {
-                let alpha = *src.next().unwrap() as u32;
-                let re_alpha = 255 - alpha;
-                let mut new = self.color.to_ne_bytes();
-                let current = buf[buf_offset].to_ne_bytes();
-
-                for i in 0..4 {
-                    new[i] = ((new[i] as u32 * alpha + current[i] as u32 * re_alpha) >> 8) as u8;
+        let buf = unsafe { std::slice::from_raw_parts_mut(self.mem as *mut u32, size) };
+
+        for y in from_y..to_y {
+            let dst_off = (y * width) as usize;
+            let src_off = ((y - y_pos) * src_width + from_x - x_pos) as usize;
+            let len = (to_x - from_x) as usize;
+            let dst_row = &mut buf[dst_off + from_x as usize..][..len];
+            let src_row = &src[src_off..][..len];
+            for (pixel, color) in dst_row.iter_mut().zip(src_row) {
+                *pixel = if color >> 24 == 0xff { *color } else { blend(*pixel, *color, 0xff) };
+            }
+        }
+    }
+
+    /// Apply an effect to the whole canvas. The result is opaque.
+    pub fn apply(&self, effect: Effect) {
+        match effect {
+            Effect::Blur(radius) => self.box_blur(radius as usize),
+            Effect::GaussianBlur(sigma) => {
+                // Three box blurs come close to a gaussian blur, see "Fast Almost-Gaussian
+                // Filtering" by Peter Kovesi. Each box has about the width of the kernel.
+                let radius = ((4.0 * (sigma as f32).powi(2) + 1.0).sqrt() - 1.0) / 2.0;
+                for _ in 0..3 {
+                    self.box_blur(radius.round() as usize);
                 }
+            }
+            Effect::Pixelate(size) => self.pixelate(size as usize),
+            Effect::Darken(amount) => self.darken(amount),
+            Effect::Brightness(amount) => self.brightness(amount),
+            Effect::Contrast(factor) => self.contrast(factor),
+            Effect::Grayscale(amount) => self.grayscale(amount),
+            Effect::Vignette(strength) => self.vignette(strength),
+            Effect::Tint(color) => self.tint(color),
+            Effect::Noise(amount) => self.noise(amount),
+        }
+    }
+
+    /// Blur with a box of `2 * radius + 1` pixels, first along the rows, then along the columns.
+    /// Pixels beyond the edges repeat the edge.
+    pub fn box_blur(&self, radius: usize) {
+        let (width, height) = self.dimensions;
+        if radius == 0 || width == 0 || height == 0 {
+            return;
+        }
 
-                buf[buf_offset] = u32::from_ne_bytes(new);
-                buf_offset += 1;
+        let buf = unsafe { std::slice::from_raw_parts_mut(self.mem as *mut u32, width * height) };
+        let mut line = Vec::with_capacity(width.max(height));
+        for row in buf.chunks_exact_mut(width) {
+            line.clear();
+            line.extend_from_slice(row);
+            blur_line(&line, radius, row.iter_mut());
+        }
+        for x in 0..width {
+            line.clear();
+            line.extend(buf.iter().skip(x).step_by(width));
+            blur_line(&line, radius, buf.iter_mut().skip(x).step_by(width));
+        }
+    }
+
+    /// Replace squares of `size` pixels by their average, starting in the top left corner.
+    pub fn pixelate(&self, size: usize) {
+        let (width, height) = self.dimensions;
+        if size <= 1 {
+            return;
+        }
+
+        let buf = unsafe { std::slice::from_raw_parts_mut(self.mem as *mut u32, width * height) };
+        for top in (0..height).step_by(size) {
+            for left in (0..width).step_by(size) {
+                let (bottom, right) = ((top + size).min(height), (left + size).min(width));
+                let mut sums = [0u64; 3];
+                for y in top..bottom {
+                    for pixel in &buf[y * width + left..y * width + right] {
+                        add(&mut sums, *pixel);
+                    }
+                }
+                let count = ((bottom - top) * (right - left)) as u64;
+                let average = pack(sums.map(|sum| (sum / count) as u32));
+                for y in top..bottom {
+                    buf[y * width + left..y * width + right].fill(average);
+                }
             }
         }
     }
 
+    /// Darken by `amount`, from 0 for no change to 1 for black.
+    pub fn darken(&self, amount: f64) {
+        let factor = ((1.0 - amount.clamp(0.0, 1.0)) * 256.0) as u32;
+        self.map_pixels(|_, pixel| pack(channels(pixel).map(|channel| channel * factor / 256)));
+    }
+
+    /// Brighten by `amount` from 0 to 1, or darken from 0 to -1, shifting every channel equally.
+    pub fn brightness(&self, amount: f64) {
+        let shift = (amount.clamp(-1.0, 1.0) * 255.0).round() as i32;
+        self.map_pixels(|_, pixel| {
+            pack(channels(pixel).map(|channel| clamp(channel as i32 + shift)))
+        });
+    }
+
+    /// Scale the distance of every channel from the middle gray by `factor`, increasing the
+    /// contrast above 1 and reducing it below.
+    pub fn contrast(&self, factor: f64) {
+        let factor = (factor.max(0.0) * 256.0) as i32;
+        self.map_pixels(|_, pixel| {
+            pack(channels(pixel).map(|channel| clamp((channel as i32 - 128) * factor / 256 + 128)))
+        });
+    }
+
+    /// Desaturate by `amount`, from 0 for no change to 1 for shades of gray.
+    pub fn grayscale(&self, amount: f64) {
+        let amount = (amount.clamp(0.0, 1.0) * 256.0) as u32;
+        self.map_pixels(|_, pixel| {
+            let [r, g, b] = channels(pixel);
+            // Rec. 601 luma in 1/256
+            let luma = (77 * r + 150 * g + 29 * b) / 256;
+            pack([r, g, b].map(|channel| (channel * (256 - amount) + luma * amount) / 256))
+        });
+    }
+
+    /// Darken towards the corners, which get darker by `strength` from 0 to 1. The center is
+    /// unchanged.
+    pub fn vignette(&self, strength: f64) {
+        let (width, height) = self.dimensions;
+        let strength = strength.clamp(0.0, 1.0);
+        let (center_x, center_y) = (width as f64 / 2.0, height as f64 / 2.0);
+        let corner = center_x * center_x + center_y * center_y;
+        if corner == 0.0 {
+            return;
+        }
+        self.map_pixels(|i, pixel| {
+            let (dx, dy) =
+                ((i % width) as f64 + 0.5 - center_x, (i / width) as f64 + 0.5 - center_y);
+            let factor = ((1.0 - strength * (dx * dx + dy * dy) / corner) * 256.0) as u32;
+            pack(channels(pixel).map(|channel| channel * factor / 256))
+        });
+    }
+
+    /// Blend `color` over the canvas, by its alpha.
+    pub fn tint(&self, color: u32) {
+        self.map_pixels(|_, pixel| blend(pixel, color, 0xff));
+    }
+
+    /// Add the same random offset of up to `amount` of the full range to every channel of a pixel.
+    /// The noise is the same on every call.
+    pub fn noise(&self, amount: f64) {
+        let range = (amount.clamp(0.0, 1.0) * 255.0) as i32;
+        if range == 0 {
+            return;
+        }
+        // xorshift32, good enough to look random and reproducible in tests
+        let mut state = 0x9e3779b9u32;
+        self.map_pixels(|_, pixel| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let offset = (state % (2 * range as u32 + 1)) as i32 - range;
+            pack(channels(pixel).map(|channel| clamp(channel as i32 + offset)))
+        });
+    }
+
+    /// Draw `gradient` over the canvas. With `dither` the colors between two levels are mixed by
+    /// a 4x4 Bayer matrix, instead of rounding to the nearer level, which shows as bands.
+    pub fn gradient(&self, gradient: &Gradient, dither: bool) {
+        let stops = gradient.positions();
+        let (width, height) = (self.dimensions.0 as f64, self.dimensions.1 as f64);
+        let (center_x, center_y) = (width / 2.0, height / 2.0);
+        let angle = gradient.angle.unwrap_or(180.0).to_radians();
+        let direction = (angle.sin(), -angle.cos());
+        // The line through the center along which the gradient runs reaches the corners
+        let length = (width * direction.0).abs() + (height * direction.1).abs();
+        let radius = center_x.hypot(center_y);
+
+        let columns = self.dimensions.0;
+        self.map_pixels(|i, pixel| {
+            let (x, y) = (i % columns, i / columns);
+            let (dx, dy) = (x as f64 + 0.5 - center_x, y as f64 + 0.5 - center_y);
+            let position = match gradient.kind {
+                GradientKind::Linear => (dx * direction.0 + dy * direction.1) / length + 0.5,
+                GradientKind::Radial => dx.hypot(dy) / radius,
+            };
+            let threshold = if dither { threshold(x, y) } else { 0.5 };
+            let channels =
+                color_at(&stops, position).map(|channel| clamp((channel + threshold) as i32));
+            let [a, r, g, b] = channels;
+            blend(pixel, a << 24 | r << 16 | g << 8 | b, 0xff)
+        });
+    }
+
+    /// Draw `pattern` over the canvas, starting in the top left corner.
+    pub fn pattern(&self, pattern: &Pattern) {
+        let size = pattern.size.max(1) as usize;
+        let columns = self.dimensions.0;
+        self.map_pixels(|i, pixel| {
+            let (x, y) = (i % columns, i / columns);
+            let coverage = match pattern.kind {
+                PatternKind::Checker => ((x / size + y / size) % 2) as u8 * 0xff,
+                PatternKind::Stripes => (((x + y) / size) % 2) as u8 * 0xff,
+                PatternKind::Dots => {
+                    // Dots of half the distance between them, anti-aliased at the edge
+                    let center = size as f64 / 2.0;
+                    let (dx, dy) =
+                        ((x % size) as f64 + 0.5 - center, (y % size) as f64 + 0.5 - center);
+                    ((size as f64 / 4.0 + 0.5 - dx.hypot(dy)).clamp(0.0, 1.0) * 255.0) as u8
+                }
+            };
+            if coverage == 0 {
+                pixel
+            } else {
+                blend(pixel, pattern.color, coverage)
+            }
+        });
+    }
+
+    /// Blend an opaque frame of the size of the canvas over it with the given opacity.
+    pub fn blend_frame(&self, frame: &[u32], opacity: u8) {
+        self.map_pixels(|i, pixel| blend(pixel, frame[i], opacity));
+    }
+
+    /// Replace every pixel by `f(index, pixel)`.
+    fn map_pixels(&self, mut f: impl FnMut(usize, u32) -> u32) {
+        let size = self.dimensions.0 * self.dimensions.1;
+        let buf = unsafe { std::slice::from_raw_parts_mut(self.mem as *mut u32, size) };
+        for (i, pixel) in buf.iter_mut().enumerate() {
+            *pixel = f(i, *pixel);
+        }
+    }
+
+    /// Blend `self.color` by the coverage in `bitmap`, clipped to the canvas.
+    fn draw_bitmap(&self, bitmap: &[u8], dimensions: (usize, usize), position: (isize, isize)) {
+        let (width, height) = (self.dimensions.0 as isize, self.dimensions.1 as isize);
+        let (src_width, src_height) = (dimensions.0 as isize, dimensions.1 as isize);
+        let (x_pos, y_pos) = position;
+
+        let (from_x, to_x) = (x_pos.max(0), (x_pos + src_width).min(width));
+        let (from_y, to_y) = (y_pos.max(0), (y_pos + src_height).min(height));
+        if from_x >= to_x || from_y >= to_y {
+            return;
+        }
+
+        let size = self.dimensions.0 * self.dimensions.1;
+        let buf = unsafe { std::slice::from_raw_parts_mut(self.mem as *mut u32, size) };
+
+        for y in from_y..to_y {
+            let dst_off = (y * width) as usize;
+            let src_off = ((y - y_pos) * src_width + from_x - x_pos) as usize;
+            let len = (to_x - from_x) as usize;
+            let dst_row = &mut buf[dst_off + from_x as usize..][..len];
+            for (pixel, coverage) in dst_row.iter_mut().zip(&bitmap[src_off..][..len]) {
+                *pixel = blend(*pixel, self.color, *coverage);
+            }
+        }
+    }
+
+    /// Append `text` to `layout`, each character in the first font of `chain` containing it.
+    /// `chain` holds indices into `fonts`, the last one is used for characters no font contains.
+    pub fn append_text(&self, layout: &mut Layout, text: &str, px: f32, chain: &[usize]) {
+        let font_for = |c: char| {
+            chain
+                .iter()
+                .copied()
+                .find(|&font| self.fonts[font].lookup_glyph_index(c) != 0)
+                .unwrap_or(*chain.last().unwrap_or(&0))
+        };
+        for (font_index, run) in runs(text, font_for) {
+            layout.append(self.fonts, &TextStyle::new(run, px, font_index));
+        }
+    }
+
     pub fn draw_layout(&self, layout: &mut Layout) {
         let glyphs = layout.glyphs();
 
@@ -64,12 +331,480 @@ impl Canvas<'_> {
                 continue;
             }
 
-            let (metrics, buf) = self.fonts[0].rasterize_config(glyph.key);
+            let (metrics, buf) = self.fonts[glyph.font_index].rasterize_config(glyph.key);
             self.draw_bitmap(
                 &buf,
                 (metrics.width, metrics.height),
-                (glyph.x as usize, glyph.y as usize),
+                (glyph.x as isize, glyph.y as isize),
             )
         }
     }
 }
+
+// Anti-aliased shapes, clipped to the canvas
+impl Canvas<'_> {
+    /// Draw a ring around `center`, with `radius` to the middle of its line.
+    pub fn stroke_circle(&self, center: (f64, f64), radius: f64, thickness: f64) {
+        self.stroke_arc(center, radius, thickness, 0.0, TAU);
+    }
+
+    /// Draw an arc of a ring around `center`, with `radius` to the middle of its line, from the
+    /// angle `start` on for `sweep`. Angles are in radians, clockwise from the top.
+    pub fn stroke_arc(
+        &self,
+        center: (f64, f64),
+        radius: f64,
+        thickness: f64,
+        start: f64,
+        sweep: f64,
+    ) {
+        self.fill_shape(around(center, radius + thickness / 2.0), |x, y| {
+            let (dx, dy) = (x - center.0, y - center.1);
+            let distance = dx.hypot(dy);
+            let radial = (distance - radius).abs() - thickness / 2.0;
+            radial.max(outside_angle(dx, dy, start, sweep) * distance)
+        });
+    }
+
+    /// Blend the color over the pixels within `bounds`, left, top, right and bottom, as far as
+    /// they are on the canvas. `distance` gives how far the center of a pixel is outside the
+    /// shape, negative within it. Pixels on the edge are covered partly, which anti-aliases it.
+    fn fill_shape(&self, bounds: [f64; 4], distance: impl Fn(f64, f64) -> f64) {
+        let (width, height) = self.dimensions;
+        // One more pixel around, which the anti-aliasing may still cover
+        let clip = |from: f64, to: f64, size: usize| {
+            let from = (from - 1.0).floor().clamp(0.0, size as f64) as usize;
+            (from, ((to + 1.0).ceil().clamp(0.0, size as f64) as usize).max(from))
+        };
+        let ((from_x, to_x), (from_y, to_y)) =
+            (clip(bounds[0], bounds[2], width), clip(bounds[1], bounds[3], height));
+
+        let buf = unsafe { std::slice::from_raw_parts_mut(self.mem as *mut u32, width * height) };
+        for y in from_y..to_y {
+            for x in from_x..to_x {
+                let coverage = (0.5 - distance(x as f64 + 0.5, y as f64 + 0.5)).clamp(0.0, 1.0);
+                if coverage > 0.0 {
+                    let pixel = &mut buf[y * width + x];
+                    *pixel = blend(*pixel, self.color, (coverage * 255.0).round() as u8);
+                }
+            }
+        }
+    }
+}
+
+/// Split `text` into runs of characters drawn with the same font. Control characters and
+/// whitespace stay with the run before them, so fallback fonts don't break up words.
+fn runs(text: &str, font_for: impl Fn(char) -> usize) -> Vec<(usize, &str)> {
+    let mut runs: Vec<(usize, &str)> = Vec::new();
+    let mut start = 0;
+    let mut current = None;
+    for (i, c) in text.char_indices() {
+        if c.is_control() || c.is_whitespace() {
+            continue;
+        }
+        let font = font_for(c);
+        match current {
+            Some(current) if current == font => {}
+            Some(current) => {
+                runs.push((current, &text[start..i]));
+                start = i;
+            }
+            None => {}
+        }
+        current = Some(font);
+    }
+    if start < text.len() {
+        runs.push((current.unwrap_or(0), &text[start..]));
+    }
+    runs
+}
+
+/// The bounds of a circle, see `Canvas::fill_shape`.
+fn around(center: (f64, f64), radius: f64) -> [f64; 4] {
+    [center.0 - radius, center.1 - radius, center.0 + radius, center.1 + radius]
+}
+
+/// How far the angle of `(dx, dy)` is outside the arc from `start` on for `sweep`, in radians and
+/// negative within it. Multiplied by the distance from the center it is about the distance in
+/// pixels along the circle.
+fn outside_angle(dx: f64, dy: f64, start: f64, sweep: f64) -> f64 {
+    if sweep >= TAU {
+        return f64::NEG_INFINITY;
+    }
+    let angle = (dx.atan2(-dy) - start).rem_euclid(TAU);
+    if angle <= sweep {
+        -angle.min(sweep - angle)
+    } else {
+        (angle - sweep).min(TAU - angle)
+    }
+}
+
+/// Box blur `line` into `out`, see `Canvas::box_blur`.
+fn blur_line<'a>(line: &[u32], radius: usize, out: impl Iterator<Item = &'a mut u32>) {
+    let last = line.len() - 1;
+    let at = |i: isize| line[i.clamp(0, last as isize) as usize];
+    let window = (2 * radius + 1) as u64;
+
+    let mut sums = [0u64; 3];
+    for i in -(radius as isize)..=radius as isize {
+        add(&mut sums, at(i));
+    }
+    for (x, pixel) in out.enumerate() {
+        *pixel = pack(sums.map(|sum| (sum / window) as u32));
+        let x = x as isize;
+        add(&mut sums, at(x + radius as isize + 1));
+        let [r, g, b] = channels(at(x - radius as isize));
+        sums[0] -= r as u64;
+        sums[1] -= g as u64;
+        sums[2] -= b as u64;
+    }
+}
+
+fn channels(pixel: u32) -> [u32; 3] {
+    [(pixel >> 16) & 0xff, (pixel >> 8) & 0xff, pixel & 0xff]
+}
+
+fn add(sums: &mut [u64; 3], pixel: u32) {
+    for (sum, channel) in sums.iter_mut().zip(channels(pixel)) {
+        *sum += channel as u64;
+    }
+}
+
+/// Thresholds of ordered dithering, in 1/16.
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// What is added to a channel at a pixel before rounding down, from 0 to 15/16.
+fn threshold(x: usize, y: usize) -> f64 {
+    BAYER[y % 4][x % 4] as f64 / 16.0
+}
+
+/// The alpha, red, green and blue of a gradient at `position`, from 0 to 255 each.
+fn color_at(stops: &[(f64, u32)], position: f64) -> [f64; 4] {
+    let argb = |color: u32| [24, 16, 8, 0].map(|shift| ((color >> shift) & 0xff) as f64);
+    let next = stops.iter().position(|(stop, _)| *stop >= position);
+    match next {
+        None => stops.last().map_or([0.0; 4], |(_, color)| argb(*color)),
+        Some(0) => argb(stops[0].1),
+        Some(i) => {
+            let ((from, start), (to, end)) = (stops[i - 1], stops[i]);
+            let t = if to > from { (position - from) / (to - from) } else { 1.0 };
+            let (start, end) = (argb(start), argb(end));
+            [0, 1, 2, 3].map(|c| start[c] + (end[c] - start[c]) * t)
+        }
+    }
+}
+
+fn clamp(channel: i32) -> u32 {
+    channel.clamp(0, 0xff) as u32
+}
+
+/// An opaque pixel of the given red, green and blue.
+fn pack([r, g, b]: [u32; 3]) -> u32 {
+    0xff000000 | r << 16 | g << 8 | b
+}
+
+/// Blend `color` over the opaque `pixel`, weighted by the alpha of `color` and the `coverage` of
+/// the pixel. Both are 0xAARRGGBB, the result stays opaque.
+fn blend(pixel: u32, color: u32, coverage: u8) -> u32 {
+    let alpha = (color >> 24) * coverage as u32 / 255;
+    let channel = |shift: u32| {
+        let (src, dst) = ((color >> shift) & 0xff, (pixel >> shift) & 0xff);
+        ((src * alpha + dst * (255 - alpha)) / 255) << shift
+    };
+    0xff000000 | channel(16) | channel(8) | channel(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blend, runs, threshold, Canvas};
+    use crate::config::{Effect, Gradient, GradientKind, Pattern, PatternKind};
+
+    #[test]
+    fn blit_clips_to_the_canvas() {
+        let mut buf = vec![0xff000000u32; 4 * 3];
+        let canvas =
+            Canvas { mem: buf.as_mut_ptr() as *mut u8, dimensions: (4, 3), color: 0, fonts: &[] };
+        let src = [0xff000001, 0xff000002, 0xff000003, 0xff000004];
+        canvas.blit(&src, (2, 2), (-1, 2));
+        canvas.blit(&src, (2, 2), (3, -1));
+        canvas.blit(&src, (2, 2), (9, 9));
+        #[rustfmt::skip]
+        assert_eq!(buf, vec![
+            0xff000000, 0xff000000, 0xff000000, 0xff000003,
+            0xff000000, 0xff000000, 0xff000000, 0xff000000,
+            0xff000002, 0xff000000, 0xff000000, 0xff000000,
+        ]);
+    }
+
+    /// Draw white on black with `f`, and return the blue channel of every pixel.
+    fn shape(dimensions: (usize, usize), f: impl FnOnce(&Canvas)) -> Vec<u32> {
+        let mut buf = vec![0xff000000; dimensions.0 * dimensions.1];
+        with_color(&mut buf, dimensions, 0xffffffff, f);
+        buf.iter().map(|pixel| pixel & 0xff).collect()
+    }
+
+    #[test]
+    fn arcs_are_anti_aliased_and_clipped() {
+        use std::f64::consts::FRAC_PI_2;
+
+        let ring = shape((21, 21), |canvas| canvas.stroke_circle((10.5, 10.5), 8.0, 2.0));
+        let at = |buf: &[u32], x: usize, y: usize| buf[y * 21 + x];
+        // On the line, half covered at its edge, untouched inside and outside
+        assert_eq!(at(&ring, 18, 10), 0xff);
+        assert_eq!(at(&ring, 10, 1), 0x80);
+        assert_eq!(at(&ring, 10, 10), 0);
+        assert_eq!(at(&ring, 0, 0), 0);
+
+        // A quarter from the top, clockwise, beyond the edges of the canvas
+        let arc = shape((21, 21), |canvas| {
+            canvas.stroke_arc((10.5, 20.5), 15.0, 4.0, 0.0, FRAC_PI_2);
+            canvas.stroke_circle((-100.0, 50.0), 5.0, 4.0);
+        });
+        assert_eq!(at(&arc, 15, 6), 0xff);
+        assert_eq!(at(&arc, 10, 5), 0x80);
+        assert_eq!(at(&arc, 5, 6), 0);
+    }
+
+    #[test]
+    fn squares_are_clipped() {
+        let square = shape((4, 2), |canvas| {
+            canvas.draw_square((3, 1), (9, 9));
+            canvas.draw_square((7, 0), (9, 1));
+        });
+        assert_eq!(square, vec![0, 0, 0, 0, 0, 0, 0, 0xff]);
+    }
+
+    #[test]
+    fn glyphs_are_clipped() {
+        #[rustfmt::skip]
+        let glyph = [
+            0xff, 0x80, 0x00,
+            0x00, 0xff, 0x80,
+        ];
+        let drawn = shape((4, 3), |canvas| {
+            // Past the right edge, past the bottom edge, and off the canvas
+            canvas.draw_bitmap(&glyph, (3, 2), (2, 0));
+            canvas.draw_bitmap(&glyph, (3, 2), (0, 2));
+            canvas.draw_bitmap(&glyph, (3, 2), (-3, 5));
+        });
+        #[rustfmt::skip]
+        assert_eq!(drawn, vec![
+            0x00, 0x00, 0xff, 0x80,
+            0x00, 0x00, 0x00, 0xff,
+            0xff, 0x80, 0x00, 0x00,
+        ]);
+    }
+
+    #[test]
+    fn blit_blends_translucent_pixels() {
+        let mut buf = vec![0xff000000u32; 2];
+        let canvas =
+            Canvas { mem: buf.as_mut_ptr() as *mut u8, dimensions: (2, 1), color: 0, fonts: &[] };
+        canvas.blit(&[0x80ffffff, 0x00ffffff], (2, 1), (0, 0));
+        assert_eq!(buf, vec![0xff808080, 0xff000000]);
+    }
+
+    #[test]
+    fn runs_split_on_font_changes() {
+        let font_for = |c: char| if c.is_ascii() { 0 } else { 1 };
+        assert_eq!(runs("User: 山田\n", font_for), vec![(0, "User: "), (1, "山田\n")]);
+        assert_eq!(runs("a 山 b", font_for), vec![(0, "a "), (1, "山 "), (0, "b")]);
+        assert_eq!(runs("\n", font_for), vec![(0, "\n")]);
+        assert!(runs("", font_for).is_empty());
+    }
+
+    /// Run `f` on a canvas over `buf`.
+    fn with_canvas(buf: &mut [u32], dimensions: (usize, usize), f: impl FnOnce(&Canvas)) {
+        with_color(buf, dimensions, 0, f);
+    }
+
+    /// Run `f` on a canvas over `buf`, drawing in `color`.
+    fn with_color(
+        buf: &mut [u32],
+        dimensions: (usize, usize),
+        color: u32,
+        f: impl FnOnce(&Canvas),
+    ) {
+        assert_eq!(buf.len(), dimensions.0 * dimensions.1);
+        f(&Canvas { mem: buf.as_mut_ptr() as *mut u8, dimensions, color, fonts: &[] });
+    }
+
+    #[test]
+    fn box_blur_spreads_evenly() {
+        let mut buf = vec![0xff000000; 5 * 5];
+        buf[12] = 0xff2d2d2d;
+        with_canvas(&mut buf, (5, 5), |canvas| canvas.box_blur(1));
+        // 0x2d / 9 = 5 in each of the 3x3 pixels around the center, nothing beyond
+        for (i, pixel) in buf.iter().enumerate() {
+            let (x, y) = (i % 5, i / 5);
+            let inside = (1..=3).contains(&x) && (1..=3).contains(&y);
+            assert_eq!(*pixel, if inside { 0xff050505 } else { 0xff000000 }, "{}, {}", x, y);
+        }
+    }
+
+    #[test]
+    fn box_blur_keeps_flat_colors() {
+        let mut buf = vec![0xff336699; 3 * 2];
+        with_canvas(&mut buf, (3, 2), |canvas| canvas.apply(Effect::GaussianBlur(4)));
+        assert_eq!(buf, vec![0xff336699; 3 * 2]);
+    }
+
+    #[test]
+    fn pixelate_averages_blocks() {
+        #[rustfmt::skip]
+        let mut buf = vec![
+            0xff000000, 0xff080808, 0xff101010,
+            0xff101010, 0xff181818, 0xff202020,
+        ];
+        with_canvas(&mut buf, (3, 2), |canvas| canvas.pixelate(2));
+        #[rustfmt::skip]
+        assert_eq!(buf, vec![
+            0xff0c0c0c, 0xff0c0c0c, 0xff181818,
+            0xff0c0c0c, 0xff0c0c0c, 0xff181818,
+        ]);
+    }
+
+    #[test]
+    fn darken_scales_channels() {
+        let mut buf = vec![0xff808080, 0xffffffff];
+        with_canvas(&mut buf, (2, 1), |canvas| canvas.darken(0.5));
+        assert_eq!(buf, vec![0xff404040, 0xff7f7f7f]);
+    }
+
+    #[test]
+    fn brightness_and_contrast_saturate() {
+        let mut buf = vec![0xff10_80f0];
+        with_canvas(&mut buf, (1, 1), |canvas| canvas.brightness(0.25));
+        assert_eq!(buf, vec![0xff50_c0ff]);
+        with_canvas(&mut buf, (1, 1), |canvas| canvas.contrast(2.0));
+        assert_eq!(buf, vec![0xff20_ffff]);
+        with_canvas(&mut buf, (1, 1), |canvas| canvas.contrast(0.0));
+        assert_eq!(buf, vec![0xff80_8080]);
+    }
+
+    #[test]
+    fn grayscale_uses_luma() {
+        let mut buf = vec![0xffff0000, 0xff00ff00, 0xffffffff];
+        with_canvas(&mut buf, (3, 1), |canvas| canvas.grayscale(1.0));
+        assert_eq!(buf, vec![0xff4c4c4c, 0xff959595, 0xffffffff]);
+
+        let mut buf = vec![0xffff0000];
+        with_canvas(&mut buf, (1, 1), |canvas| canvas.grayscale(0.5));
+        assert_eq!(buf, vec![0xffa52626]);
+    }
+
+    #[test]
+    fn vignette_darkens_the_corners() {
+        let mut buf = vec![0xffffffff; 5 * 5];
+        with_canvas(&mut buf, (5, 5), |canvas| canvas.vignette(1.0));
+        assert_eq!(buf[12], 0xffffffff);
+        // The corner pixel is 8/12.5 of the way from the center to the corner of the canvas
+        assert_eq!(buf[0], 0xff5b5b5b);
+        assert_eq!(buf[0], buf[24]);
+        assert!(buf[2] > buf[0] && buf[2] < buf[12]);
+    }
+
+    #[test]
+    fn tint_blends_by_alpha() {
+        let mut buf = vec![0xff000000, 0xffffffff];
+        with_canvas(&mut buf, (2, 1), |canvas| canvas.tint(0x80ff0000));
+        assert_eq!(buf, vec![0xff800000, 0xffff7f7f]);
+    }
+
+    #[test]
+    fn noise_stays_in_range_and_repeats() {
+        let mut first = vec![0xff808080; 64];
+        with_canvas(&mut first, (8, 8), |canvas| canvas.noise(0.1));
+        let mut second = vec![0xff808080; 64];
+        with_canvas(&mut second, (8, 8), |canvas| canvas.noise(0.1));
+        assert_eq!(first, second);
+        assert!(first.iter().any(|pixel| *pixel != 0xff808080));
+        for pixel in first {
+            let [r, g, b] = [(pixel >> 16) & 0xff, (pixel >> 8) & 0xff, pixel & 0xff];
+            assert!(r == g && g == b && (0x80 - 25..=0x80 + 25).contains(&r), "{:x}", pixel);
+        }
+    }
+
+    #[test]
+    fn linear_gradients_follow_the_angle() {
+        let stops = vec!["#000000".parse().unwrap(), "#ff0000".parse().unwrap()];
+        let gradient = |angle| Gradient { kind: GradientKind::Linear, angle, stops: stops.clone() };
+
+        // Top to bottom by default, each row a step further
+        let mut buf = vec![0; 2 * 4];
+        with_canvas(&mut buf, (2, 4), |canvas| canvas.gradient(&gradient(None), false));
+        let red: Vec<_> = buf.iter().map(|pixel| (pixel >> 16) & 0xff).collect();
+        assert_eq!(red, vec![0x20, 0x20, 0x60, 0x60, 0x9f, 0x9f, 0xdf, 0xdf]);
+
+        // Right to left
+        let mut buf = vec![0; 4];
+        with_canvas(&mut buf, (4, 1), |canvas| canvas.gradient(&gradient(Some(270.0)), false));
+        assert_eq!(buf, vec![0xffdf0000, 0xff9f0000, 0xff600000, 0xff200000]);
+    }
+
+    #[test]
+    fn radial_gradients_blend_by_alpha() {
+        let stops = vec!["#ffffff".parse().unwrap(), "#ffffff00".parse().unwrap()];
+        let gradient = Gradient { kind: GradientKind::Radial, angle: None, stops };
+        let mut buf = vec![0xff000000; 3 * 3];
+        with_canvas(&mut buf, (3, 3), |canvas| canvas.gradient(&gradient, false));
+        assert_eq!(buf[4], 0xffffffff);
+        assert_eq!(buf[0], buf[8]);
+        assert!(buf[1] > buf[0] && buf[1] < buf[4]);
+    }
+
+    #[test]
+    fn dithering_keeps_the_level_on_average() {
+        // A channel of 1.25 is 2 for a quarter of the pixels of every 4x4 square, 1 for the rest
+        for (x0, y0) in [(0, 0), (4, 0), (2, 6)] {
+            let levels: Vec<_> = (0..16)
+                .map(|i| (1.25 + threshold(x0 + i % 4, y0 + i / 4)).floor() as u32)
+                .collect();
+            assert_eq!(levels.iter().filter(|level| **level == 2).count(), 4);
+            assert_eq!(levels.iter().filter(|level| **level == 1).count(), 12);
+        }
+    }
+
+    #[test]
+    fn patterns_repeat() {
+        let pattern = |kind| Pattern { kind, size: 2, color: 0xffffffff };
+        let draw = |kind| {
+            let mut buf = vec![0xff000000; 4 * 2];
+            with_canvas(&mut buf, (4, 2), |canvas| canvas.pattern(&pattern(kind)));
+            buf.iter().map(|pixel| (pixel & 0xff) as u8).collect::<Vec<_>>()
+        };
+
+        assert_eq!(draw(PatternKind::Checker), vec![0, 0, 255, 255, 0, 0, 255, 255]);
+        assert_eq!(draw(PatternKind::Stripes), vec![0, 0, 255, 255, 0, 255, 255, 0]);
+        let mut buf = vec![0xff000000; 8 * 8];
+        let dots = Pattern { kind: PatternKind::Dots, size: 8, color: 0xffffffff };
+        with_canvas(&mut buf, (8, 8), |canvas| canvas.pattern(&dots));
+        assert_eq!(buf[0], 0xff000000);
+        assert_eq!(buf[3 * 8 + 3], 0xffffffff);
+        assert_eq!(buf[3 * 8 + 3], buf[4 * 8 + 4]);
+    }
+
+    #[test]
+    fn frames_blend_by_opacity() {
+        let mut buf = vec![0xff000000, 0xffffffff];
+        with_canvas(&mut buf, (2, 1), |canvas| canvas.blend_frame(&[0xffffffff, 0xff000000], 0x40));
+        assert_eq!(buf, vec![0xff404040, 0xffbfbfbf]);
+    }
+
+    #[test]
+    fn blend_opaque_replaces() {
+        assert_eq!(blend(0xff123456, 0xffabcdef, 0xff), 0xffabcdef);
+    }
+
+    #[test]
+    fn blend_transparent_keeps() {
+        assert_eq!(blend(0xff123456, 0x00abcdef, 0xff), 0xff123456);
+        assert_eq!(blend(0xff123456, 0xffabcdef, 0), 0xff123456);
+    }
+
+    #[test]
+    fn blend_half() {
+        assert_eq!(blend(0xff000000, 0x80ffffff, 0xff), 0xff808080);
+        assert_eq!(blend(0xff000000, 0xffffffff, 0x80), 0xff808080);
+    }
+}
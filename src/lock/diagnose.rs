@@ -0,0 +1,205 @@
+use super::auth::PAM_SERVICE;
+use super::env::{LockEnv, OPTIONAL_GLOBALS, REQUIRED_GLOBALS};
+use super::output::{OutputHandling, OutputInfo};
+use crate::config::{Config, Options};
+use crate::supervisor::exit_code;
+
+use serde::Serialize;
+use smithay_client_toolkit::seat;
+
+use std::fmt;
+
+/// Everything needed to tell why waylock fails on a compositor, meant for bug reports.
+#[derive(Serialize)]
+struct Report {
+    compositor_error: Option<String>,
+    globals: Vec<Global>,
+    missing_globals: Vec<&'static str>,
+    outputs: Vec<Output>,
+    seats: Vec<Seat>,
+    pam_service: &'static str,
+    fonts: Vec<String>,
+    config: Options,
+}
+
+#[derive(Serialize)]
+struct Global {
+    interface: String,
+    version: u32,
+    usage: Usage,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Usage {
+    Required,
+    Optional,
+    Unused,
+}
+
+#[derive(Serialize)]
+struct Output {
+    id: u32,
+    #[serde(flatten)]
+    info: OutputInfo,
+}
+
+#[derive(Serialize)]
+struct Seat {
+    name: String,
+    keyboard: bool,
+    pointer: bool,
+    touch: bool,
+}
+
+/// Print the diagnostics to stdout and return the exit code.
+pub fn diagnose(options: Options, config: &Config, json: bool) -> i32 {
+    let mut report = Report {
+        compositor_error: None,
+        globals: Vec::new(),
+        missing_globals: Vec::new(),
+        outputs: Vec::new(),
+        seats: Vec::new(),
+        pam_service: PAM_SERVICE,
+        fonts: config.font_names.clone(),
+        config: options,
+    };
+
+    let mut code = 0;
+    match LockEnv::init_environment() {
+        Ok((lock_env, _display, _queue)) => {
+            report.globals = lock_env
+                .manager
+                .list()
+                .into_iter()
+                .map(|(_, interface, version)| Global {
+                    usage: usage(&interface),
+                    interface,
+                    version,
+                })
+                .collect();
+            report.missing_globals = REQUIRED_GLOBALS
+                .iter()
+                .filter(|name| !report.globals.iter().any(|global| global.interface == **name))
+                .copied()
+                .collect();
+            report.outputs = lock_env
+                .get_output_info()
+                .into_iter()
+                .map(|(id, info)| Output { id, info })
+                .collect();
+            report.seats = lock_env
+                .get_all_seats()
+                .iter()
+                .filter_map(|seat| {
+                    seat::with_seat_data(seat, |data| Seat {
+                        name: data.name.clone(),
+                        keyboard: data.has_keyboard,
+                        pointer: data.has_pointer,
+                        touch: data.has_touch,
+                    })
+                })
+                .collect();
+
+            if !report.missing_globals.is_empty() {
+                code = exit_code::MISSING_GLOBAL;
+            }
+        }
+        Err(err) => {
+            code = err.exit_code();
+            report.compositor_error = Some(err.to_string());
+        }
+    }
+
+    if json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(err) => {
+                eprintln!("Could not serialize the diagnostics: {}", err);
+                return exit_code::CRASH;
+            }
+        }
+    } else {
+        print!("{}", report);
+    }
+
+    code
+}
+
+fn usage(interface: &str) -> Usage {
+    if REQUIRED_GLOBALS.contains(&interface) {
+        Usage::Required
+    } else if OPTIONAL_GLOBALS.contains(&interface) {
+        Usage::Optional
+    } else {
+        Usage::Unused
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(err) = &self.compositor_error {
+            writeln!(f, "Compositor: {}", err)?;
+        }
+
+        writeln!(f, "Globals:")?;
+        for global in &self.globals {
+            let usage = match global.usage {
+                Usage::Required => "required",
+                Usage::Optional => "optional",
+                Usage::Unused => "",
+            };
+            writeln!(f, "  {:<48} v{:<3} {}", global.interface, global.version, usage)?;
+        }
+        for name in &self.missing_globals {
+            writeln!(f, "  {:<48} MISSING, required", name)?;
+        }
+
+        writeln!(f, "Outputs:")?;
+        for output in &self.outputs {
+            let info = &output.info;
+            writeln!(f, "  {}: {} {}, scale {}", output.id, info.make, info.model, info.scale)?;
+            for mode in &info.modes {
+                write!(
+                    f,
+                    "    {}x{} @ {}.{:03} Hz",
+                    mode.width,
+                    mode.height,
+                    mode.refresh / 1000,
+                    mode.refresh % 1000
+                )?;
+                match (mode.current, mode.preferred) {
+                    (true, true) => writeln!(f, " (current, preferred)")?,
+                    (true, false) => writeln!(f, " (current)")?,
+                    (false, true) => writeln!(f, " (preferred)")?,
+                    (false, false) => writeln!(f)?,
+                }
+            }
+        }
+
+        writeln!(f, "Seats:")?;
+        for seat in &self.seats {
+            let capabilities: Vec<&str> =
+                [(seat.keyboard, "keyboard"), (seat.pointer, "pointer"), (seat.touch, "touch")]
+                    .iter()
+                    .filter(|(present, _)| *present)
+                    .map(|(_, name)| *name)
+                    .collect();
+            writeln!(f, "  {}: {}", seat.name, capabilities.join(", "))?;
+        }
+
+        writeln!(f, "PAM service: {}", self.pam_service)?;
+        writeln!(f, "Fonts: {}", self.fonts.join(", "))?;
+
+        writeln!(f, "Effective config:")?;
+        match self.config.to_toml() {
+            Ok(config) => {
+                for line in config.lines() {
+                    writeln!(f, "  {}", line)?;
+                }
+                Ok(())
+            }
+            Err(err) => writeln!(f, "  could not be serialized: {}", err),
+        }
+    }
+}
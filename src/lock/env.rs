@@ -1,24 +1,41 @@
-use super::output::{LockOutputHandler, OutputHandling};
+use super::output::{LockOutputHandler, OutputHandling, OutputInfo};
 
 use smithay_client_toolkit::{
     environment,
     environment::{Environment, SimpleGlobal},
     reexports::{
         client::protocol::{wl_compositor, wl_output, wl_seat, wl_shm},
-        client::{Attached, DispatchData, Display, EventQueue, Proxy},
+        client::{Attached, DispatchData, Display, EventQueue, Interface, Proxy},
         protocols::wlr::unstable::input_inhibitor::v1::client::zwlr_input_inhibit_manager_v1,
         protocols::wlr::unstable::layer_shell::v1::client::zwlr_layer_shell_v1,
+        protocols::wlr::unstable::screencopy::v1::client::zwlr_screencopy_manager_v1,
     },
     seat::{SeatData, SeatHandler, SeatHandling, SeatListener},
     shm::ShmHandler,
 };
 
-use std::io;
+use super::LockError;
+
+/// Globals the screen can't be locked without.
+pub const REQUIRED_GLOBALS: &[&str] = &[
+    wl_compositor::WlCompositor::NAME,
+    wl_shm::WlShm::NAME,
+    zwlr_layer_shell_v1::ZwlrLayerShellV1::NAME,
+    zwlr_input_inhibit_manager_v1::ZwlrInputInhibitManagerV1::NAME,
+];
+
+/// Globals used whenever the compositor advertises them.
+pub const OPTIONAL_GLOBALS: &[&str] = &[
+    wl_output::WlOutput::NAME,
+    wl_seat::WlSeat::NAME,
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1::NAME,
+];
 
 pub struct LockEnv {
     compositor: SimpleGlobal<wl_compositor::WlCompositor>,
     layer_shell: SimpleGlobal<zwlr_layer_shell_v1::ZwlrLayerShellV1>,
     inhibitor_manager: SimpleGlobal<zwlr_input_inhibit_manager_v1::ZwlrInputInhibitManagerV1>,
+    screencopy: SimpleGlobal<zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1>,
     shm: ShmHandler,
     outputs: LockOutputHandler,
     seats: SeatHandler,
@@ -38,6 +55,7 @@ environment!(LockEnv,
         wl_compositor::WlCompositor => compositor,
         zwlr_layer_shell_v1::ZwlrLayerShellV1 => layer_shell,
         zwlr_input_inhibit_manager_v1::ZwlrInputInhibitManagerV1 => inhibitor_manager,
+        zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1 => screencopy,
         wl_shm::WlShm => shm,
     ],
     multis = [
@@ -47,11 +65,8 @@ environment!(LockEnv,
 );
 
 impl LockEnv {
-    pub fn init_environment() -> io::Result<(Environment<Self>, Display, EventQueue)> {
-        let display = Display::connect_to_env().unwrap_or_else(|err| {
-            log::error!("Failed to connect to a wayland server: {}", err);
-            panic!();
-        });
+    pub fn init_environment() -> Result<(Environment<Self>, Display, EventQueue), LockError> {
+        let display = Display::connect_to_env().map_err(LockError::Connection)?;
         let mut queue = display.create_event_queue();
         let lock_env = Environment::init(
             &Proxy::clone(&display).attach(queue.token()),
@@ -59,6 +74,7 @@ impl LockEnv {
                 compositor: SimpleGlobal::new(),
                 layer_shell: SimpleGlobal::new(),
                 inhibitor_manager: SimpleGlobal::new(),
+                screencopy: SimpleGlobal::new(),
                 shm: ShmHandler::new(),
                 outputs: LockOutputHandler::new(),
                 seats: SeatHandler::new(),
@@ -81,6 +97,10 @@ impl LockEnv {
     pub fn set_output_removed_listener<F: Fn(u32) + 'static>(&mut self, listener: Option<F>) {
         self.outputs.set_removed_listener(listener)
     }
+
+    pub fn get_output_info(&self) -> Vec<(u32, OutputInfo)> {
+        self.outputs.info()
+    }
 }
 
 impl OutputHandling for Environment<LockEnv> {
@@ -94,4 +114,8 @@ impl OutputHandling for Environment<LockEnv> {
     fn set_output_removed_listener<F: Fn(u32) + 'static>(&self, listener: Option<F>) {
         self.with_inner(move |inner| inner.set_output_removed_listener(listener))
     }
+
+    fn get_output_info(&self) -> Vec<(u32, OutputInfo)> {
+        self.with_inner(|inner| inner.get_output_info())
+    }
 }
@@ -1,11 +1,63 @@
+use serde::Serialize;
 use smithay_client_toolkit::{
     environment::MultiGlobalHandler,
     reexports::client::protocol::{wl_output, wl_registry},
     reexports::client::{Attached, DispatchData},
 };
 
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Properties advertised by the compositor for an output.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct OutputInfo {
+    pub make: String,
+    pub model: String,
+    pub scale: i32,
+    pub modes: Vec<OutputMode>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct OutputMode {
+    pub width: i32,
+    pub height: i32,
+    /// Refresh rate in mHz
+    pub refresh: i32,
+    pub current: bool,
+    pub preferred: bool,
+}
+
+impl OutputInfo {
+    fn handle_event(&mut self, event: wl_output::Event) {
+        match event {
+            wl_output::Event::Geometry { make, model, .. } => {
+                self.make = make;
+                self.model = model;
+            }
+            wl_output::Event::Mode { flags, width, height, refresh } => {
+                let current = flags.contains(wl_output::Mode::Current);
+                if current {
+                    self.modes.iter_mut().for_each(|mode| mode.current = false);
+                }
+                self.modes.retain(|mode| {
+                    (mode.width, mode.height, mode.refresh) != (width, height, refresh)
+                });
+                self.modes.push(OutputMode {
+                    width,
+                    height,
+                    refresh,
+                    current,
+                    preferred: flags.contains(wl_output::Mode::Preferred),
+                });
+            }
+            wl_output::Event::Scale { factor } => self.scale = factor,
+            _ => {}
+        }
+    }
+}
+
 pub struct LockOutputHandler {
-    outputs: Vec<(u32, Attached<wl_output::WlOutput>)>,
+    outputs: Vec<(u32, Attached<wl_output::WlOutput>, Rc<RefCell<OutputInfo>>)>,
     created_listener: Option<Box<dyn Fn(u32, wl_output::WlOutput) + 'static>>,
     removed_listener: Option<Box<dyn Fn(u32) + 'static>>,
 }
@@ -20,7 +72,7 @@ impl LockOutputHandler {
         listener: Option<F>,
     ) {
         self.created_listener = listener.map(|f| {
-            for (id, output) in &self.outputs {
+            for (id, output, _) in &self.outputs {
                 f(*id, output.detach());
             }
             Box::new(f) as _
@@ -30,6 +82,10 @@ impl LockOutputHandler {
     pub fn set_removed_listener<F: Fn(u32) + 'static>(&mut self, listener: Option<F>) {
         self.removed_listener = listener.map(|f| Box::new(f) as _);
     }
+
+    pub fn info(&self) -> Vec<(u32, OutputInfo)> {
+        self.outputs.iter().map(|(id, _, info)| (*id, info.borrow().clone())).collect()
+    }
 }
 
 impl MultiGlobalHandler<wl_output::WlOutput> for LockOutputHandler {
@@ -41,8 +97,10 @@ impl MultiGlobalHandler<wl_output::WlOutput> for LockOutputHandler {
         _data: DispatchData,
     ) {
         let output = registry.bind::<wl_output::WlOutput>(version, id);
-        output.quick_assign(|_, _, _| { /* ignore all events */ });
-        self.outputs.push((id, (*output).clone()));
+        let info = Rc::new(RefCell::new(OutputInfo { scale: 1, ..OutputInfo::default() }));
+        let info_handle = Rc::clone(&info);
+        output.quick_assign(move |_, event, _| info_handle.borrow_mut().handle_event(event));
+        self.outputs.push((id, (*output).clone(), info));
         if let Some(listener) = &self.created_listener {
             listener(id, output.detach());
         }
@@ -52,7 +110,7 @@ impl MultiGlobalHandler<wl_output::WlOutput> for LockOutputHandler {
         if let Some(listener) = &self.removed_listener {
             listener(id);
         }
-        self.outputs.retain(|(i, o)| {
+        self.outputs.retain(|(i, o, _)| {
             if *i == id {
                 o.release();
                 false
@@ -63,7 +121,7 @@ impl MultiGlobalHandler<wl_output::WlOutput> for LockOutputHandler {
     }
 
     fn get_all(&self) -> Vec<Attached<wl_output::WlOutput>> {
-        self.outputs.iter().map(|(_, o)| o.clone()).collect()
+        self.outputs.iter().map(|(_, o, _)| o.clone()).collect()
     }
 }
 
@@ -74,4 +132,6 @@ pub trait OutputHandling {
     );
 
     fn set_output_removed_listener<F: Fn(u32) + 'static>(&self, listener: Option<F>);
+
+    fn get_output_info(&self) -> Vec<(u32, OutputInfo)>;
 }
@@ -0,0 +1,202 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use image::RgbaImage;
+use smithay_client_toolkit::{
+    reexports::{
+        client::protocol::{wl_buffer, wl_output, wl_shm},
+        client::{Attached, Main},
+        protocols::wlr::unstable::screencopy::v1::client::{
+            zwlr_screencopy_frame_v1, zwlr_screencopy_manager_v1,
+        },
+    },
+    shm::MemPool,
+};
+
+/// How long an output may stay unlocked while waiting for its screenshot.
+const CAPTURE_TIMEOUT: Duration = Duration::from_millis(500);
+
+type Done = Box<dyn FnOnce(Option<RgbaImage>)>;
+
+/// A screenshot of an output that is being taken. The callback passed to `capture` runs exactly
+/// once, unless the capture is dropped before it finishes.
+pub struct Capture {
+    frame: Main<zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1>,
+    done: Rc<RefCell<Option<Done>>>,
+    started: Instant,
+}
+
+/// The shm buffer a frame is copied into.
+struct Target {
+    format: wl_shm::Format,
+    dimensions: (u32, u32),
+    stride: u32,
+    pool: MemPool,
+    buffer: wl_buffer::WlBuffer,
+}
+
+/// Take a screenshot of `output`, without the cursor. `done` gets the screenshot, or `None` if
+/// the compositor could not provide it in a format waylock understands.
+pub fn capture<F: FnOnce(Option<RgbaImage>) + 'static>(
+    manager: &Attached<zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1>,
+    shm: Attached<wl_shm::WlShm>,
+    output: &wl_output::WlOutput,
+    done: F,
+) -> Capture {
+    let done: Rc<RefCell<Option<Done>>> = Rc::new(RefCell::new(Some(Box::new(done))));
+    let finish = {
+        let done = done.clone();
+        move |screenshot| {
+            // Taken out first, the callback may create surfaces that dispatch more events
+            let done = done.borrow_mut().take();
+            if let Some(done) = done {
+                done(screenshot);
+            }
+        }
+    };
+
+    let frame = manager.capture_output(0, output);
+    let mut format = None;
+    let mut target = None;
+    let mut y_invert = false;
+    frame.quick_assign(move |frame, event, _| {
+        use zwlr_screencopy_frame_v1::Event;
+        match event {
+            Event::Buffer { format: buffer_format, width, height, stride } => {
+                if format.is_none() && to_rgba(buffer_format, [0; 4]).is_some() {
+                    format = Some((buffer_format, (width, height), stride));
+                }
+                // Before version 3 there is no event after the last buffer type
+                if frame.as_ref().version() < 3 {
+                    target = copy(&frame, &shm, format.take());
+                    if target.is_none() {
+                        frame.destroy();
+                        finish(None);
+                    }
+                }
+            }
+            Event::BufferDone => {
+                target = copy(&frame, &shm, format.take());
+                if target.is_none() {
+                    log::warn!("The compositor offers no supported format for screenshots");
+                    frame.destroy();
+                    finish(None);
+                }
+            }
+            Event::Flags { flags } => {
+                y_invert = flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert);
+            }
+            Event::Ready { .. } => {
+                let screenshot = target.take().map(|mut target| {
+                    let screenshot = read(&mut target, y_invert);
+                    target.buffer.destroy();
+                    screenshot
+                });
+                frame.destroy();
+                finish(screenshot);
+            }
+            Event::Failed => {
+                log::warn!("The compositor could not take a screenshot");
+                if let Some(target) = target.take() {
+                    target.buffer.destroy();
+                }
+                frame.destroy();
+                finish(None);
+            }
+            _ => {}
+        }
+    });
+
+    Capture { frame, done, started: Instant::now() }
+}
+
+impl Capture {
+    /// Whether the callback has yet to run.
+    pub fn pending(&self) -> bool {
+        self.done.borrow().is_some()
+    }
+
+    /// Whether the compositor took too long to provide the screenshot.
+    pub fn expired(&self) -> bool {
+        self.started.elapsed() > CAPTURE_TIMEOUT
+    }
+
+    /// Give up on the screenshot and run the callback without it.
+    pub fn cancel(self) {
+        let done = self.done.borrow_mut().take();
+        self.frame.destroy();
+        if let Some(done) = done {
+            done(None);
+        }
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        if self.pending() {
+            self.frame.destroy();
+        }
+    }
+}
+
+/// Allocate a buffer in the chosen format and ask the compositor to copy the frame into it.
+fn copy(
+    frame: &Main<zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1>,
+    shm: &Attached<wl_shm::WlShm>,
+    format: Option<(wl_shm::Format, (u32, u32), u32)>,
+) -> Option<Target> {
+    let (format, dimensions, stride) = format?;
+    let mut pool = match MemPool::new(shm.clone(), |_| {}) {
+        Ok(pool) => pool,
+        Err(err) => {
+            log::warn!("Failed to create shared memory for a screenshot: {}", err);
+            return None;
+        }
+    };
+    if let Err(err) = pool.resize(stride as usize * dimensions.1 as usize) {
+        log::warn!("Failed to create shared memory for a screenshot: {}", err);
+        return None;
+    }
+    let buffer = pool.buffer(0, dimensions.0 as i32, dimensions.1 as i32, stride as i32, format);
+    frame.copy(&buffer);
+    Some(Target { format, dimensions, stride, pool, buffer })
+}
+
+/// Convert the copied frame to an opaque RGBA image.
+fn read(target: &mut Target, y_invert: bool) -> RgbaImage {
+    let (width, height) = target.dimensions;
+    let (format, stride) = (target.format, target.stride as usize);
+    let mem = target.pool.mmap();
+    RgbaImage::from_fn(width, height, |x, y| {
+        let row = if y_invert { height - 1 - y } else { y } as usize;
+        let offset = row * stride + x as usize * 4;
+        let bytes = [mem[offset], mem[offset + 1], mem[offset + 2], mem[offset + 3]];
+        image::Rgba(to_rgba(format, bytes).unwrap_or([0, 0, 0, 0xff]))
+    })
+}
+
+/// Reorder the little endian `bytes` of a pixel in `format` to RGBA, dropping the alpha channel.
+fn to_rgba(format: wl_shm::Format, bytes: [u8; 4]) -> Option<[u8; 4]> {
+    let [b0, b1, b2, _] = bytes;
+    match format {
+        wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888 => Some([b2, b1, b0, 0xff]),
+        wl_shm::Format::Abgr8888 | wl_shm::Format::Xbgr8888 => Some([b0, b1, b2, 0xff]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay_client_toolkit::reexports::client::protocol::wl_shm::Format;
+
+    use super::to_rgba;
+
+    #[test]
+    fn formats_are_reordered_to_rgba() {
+        let bytes = [0x11, 0x22, 0x33, 0x00];
+        assert_eq!(to_rgba(Format::Xrgb8888, bytes), Some([0x33, 0x22, 0x11, 0xff]));
+        assert_eq!(to_rgba(Format::Abgr8888, bytes), Some([0x11, 0x22, 0x33, 0xff]));
+        assert_eq!(to_rgba(Format::Rgb565, bytes), None);
+    }
+}
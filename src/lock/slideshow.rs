@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::RgbaImage;
+use smithay_client_toolkit::reexports::calloop::channel::Sender;
+
+use crate::config::load_image;
+
+/// The images of a background directory, shown to every output in its own random order.
+pub struct Slideshow {
+    images: Vec<PathBuf>,
+    /// The order of the images and the position in it, per output
+    outputs: HashMap<u32, (Vec<usize>, usize)>,
+    /// Decoded images are sent to the event loop with the output they are for
+    sender: Sender<(u32, RgbaImage)>,
+    random: u32,
+}
+
+impl Slideshow {
+    pub fn new(sender: Sender<(u32, RgbaImage)>) -> Self {
+        let seed =
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.subsec_nanos());
+        Slideshow { images: Vec::new(), outputs: HashMap::new(), sender, random: seed | 1 }
+    }
+
+    /// Replace the images, e.g. after the configuration was reloaded. Returns whether they
+    /// changed, then every output starts over.
+    pub fn set_images(&mut self, images: Vec<PathBuf>) -> bool {
+        if images == self.images {
+            return false;
+        }
+        self.images = images;
+        self.outputs.clear();
+        true
+    }
+
+    /// Decode the next image of an output on another thread, so input is never blocked by it.
+    /// An image that can't be decoded is skipped with a warning.
+    pub fn next(&mut self, id: u32) {
+        if self.images.is_empty() {
+            return;
+        }
+        let position = match self.outputs.get_mut(&id) {
+            Some((order, position)) if *position + 1 < order.len() => {
+                *position += 1;
+                order[*position]
+            }
+            // Shuffle again after every image was shown
+            _ => {
+                let order = self.shuffled();
+                let first = order[0];
+                self.outputs.insert(id, (order, 0));
+                first
+            }
+        };
+
+        let path = self.images[position].clone();
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            if let Some(image) = load_image(&path) {
+                // Fails only if the screen was unlocked in the meantime
+                let _ = sender.send((id, image));
+            }
+        });
+    }
+
+    /// Forget an output that was removed.
+    pub fn remove(&mut self, id: u32) {
+        self.outputs.remove(&id);
+    }
+
+    /// The indices of the images in a random order.
+    fn shuffled(&mut self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.images.len()).collect();
+        // Fisher-Yates, with xorshift32 as it needs no cryptographic quality
+        for i in (1..order.len()).rev() {
+            self.random ^= self.random << 13;
+            self.random ^= self.random >> 17;
+            self.random ^= self.random << 5;
+            order.swap(i, self.random as usize % (i + 1));
+        }
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay_client_toolkit::reexports::calloop::channel::channel;
+
+    use super::Slideshow;
+
+    #[test]
+    fn every_image_is_shown_once_per_round() {
+        let mut slideshow = Slideshow::new(channel().0);
+        slideshow.set_images((0..5).map(|i| format!("/nonexistent/{}.png", i).into()).collect());
+        let mut shown = Vec::new();
+        for _ in 0..10 {
+            slideshow.next(7);
+            let (order, position) = &slideshow.outputs[&7];
+            shown.push(order[*position]);
+        }
+
+        for round in shown.chunks(5) {
+            let mut round = round.to_vec();
+            round.sort_unstable();
+            assert_eq!(round, vec![0, 1, 2, 3, 4]);
+        }
+        assert!(!slideshow.set_images(slideshow.images.clone()));
+        assert!(slideshow.set_images(Vec::new()));
+        assert!(slideshow.outputs.is_empty());
+    }
+}
@@ -11,16 +11,38 @@ use smithay_client_toolkit::{
 
 use fontdue::layout::*;
 
+use crate::lock::animation::Animation;
+use crate::lock::background;
 use crate::lock::canvas::Canvas;
 use chrono::Timelike;
 use std::cell::Cell;
-use std::cmp::min;
+use std::cmp::{min, Ordering};
+use std::f64::consts::{PI, TAU};
 use std::rc::Rc;
 use std::{error, fmt, io};
-use crate::config::Config;
-use crate::lock::LockState;
+use crate::config::{Config, IndicatorStyle, ScaleMode, MAX_ANIMATION_BYTES};
+use image::RgbaImage;
+use std::time::{Duration, Instant};
+use crate::lock::{LockError, LockState};
 use std::sync::Arc;
 
+/// Height of the banner shown when reloading the configuration failed.
+const BANNER_HEIGHT: usize = 32;
+/// How much of the ring is highlighted on a keypress or backspace, in radians.
+const HIGHLIGHT_SWEEP: f64 = PI / 3.0;
+/// How far the highlight moves on for each keypress or backspace. The golden angle never brings it
+/// back to the same place, so that every keypress can be seen.
+const HIGHLIGHT_STEP: f64 = 2.399_963;
+/// How long a keypress or backspace stays highlighted.
+const HIGHLIGHT_DURATION: Duration = Duration::from_millis(500);
+
+/// The last change to the password, highlighted on the ring.
+#[derive(Clone, Copy)]
+enum Highlight {
+    Key,
+    Backspace,
+}
+
 #[derive(PartialEq, Copy, Clone)]
 enum RenderEvent {
     Configure { width: u32, height: u32 },
@@ -57,6 +79,16 @@ impl fmt::Display for DrawError {
     }
 }
 
+/// A background rendered for the dimensions it is stored with, `None` until it is rendered.
+type Rendered = Option<((usize, usize), Vec<u32>)>;
+
+/// The previous background while the next image of the slideshow fades in.
+struct Fade {
+    started: Instant,
+    dimensions: (usize, usize),
+    frame: Vec<u32>,
+}
+
 pub struct LockSurface {
     surface: Main<wl_surface::WlSurface>,
     layer_surface: Main<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1>,
@@ -65,8 +97,31 @@ pub struct LockSurface {
     dimensions: (usize, usize),
     redraw: bool,
     chars_entered: u32,
+    /// The last change to the password and where on the ring it is highlighted.
+    highlight: Option<(Highlight, f64)>,
+    /// When the highlight is cleared, `None` once it is.
+    highlight_ends: Option<Instant>,
+    failed_attempts: u32,
     state: LockState,
+    /// Whether a buffer was committed, so the output is covered.
+    mapped: bool,
     config: Arc<Config>,
+    safe_mode: bool,
+    reload_error: Option<String>,
+    /// The background image rendered for the dimensions it is stored with.
+    background: Rendered,
+    /// What the output showed before it was locked, if `background.screenshot` is enabled.
+    screenshot: Option<RgbaImage>,
+    /// The current image of the slideshow, if the background image is a directory.
+    slide: Option<RgbaImage>,
+    fade: Option<Fade>,
+    /// Which frame of an animated background image is shown, if it is played.
+    animation: Option<Animation>,
+    /// The frames of the animation, each rendered for the dimensions it is stored with.
+    frames: Vec<Rendered>,
+    /// Whether the compositor has yet to ask for the next frame. Frames are only drawn once it
+    /// does, so nothing is drawn for outputs that are off or hidden.
+    frame_pending: Rc<Cell<bool>>,
 }
 
 impl LockSurface {
@@ -75,8 +130,14 @@ impl LockSurface {
         compositor: &Attached<wl_compositor::WlCompositor>,
         layer_shell: &Attached<zwlr_layer_shell_v1::ZwlrLayerShellV1>,
         shm: Attached<wl_shm::WlShm>,
-        config: Arc<Config>
-    ) -> Self {
+        config: Arc<Config>,
+        safe_mode: bool,
+        screenshot: Option<RgbaImage>,
+    ) -> Result<Self, LockError> {
+        // TODO: this callback should technically trigger a redraw, however it is currently very
+        // unlikely to be reached
+        let pools = DoubleMemPool::new(shm, |_| {}).map_err(LockError::Shm)?;
+
         let surface = compositor.create_surface();
         // We don't currently care about dpi awareness, but that may need to change eventually
         surface.quick_assign(|_, _, _| {});
@@ -122,24 +183,32 @@ impl LockSurface {
         // Commit so that the server will send a configure event
         surface.commit();
 
-        // TODO: this callback should technically trigger a redraw, however it is currently very
-        // unlikely to be reached
-        let pools = DoubleMemPool::new(shm, |_| {}).unwrap_or_else(|err| {
-            log::error!("Failed to create shm pools: {}", err);
-            panic!();
-        });
-
-        Self {
+        let mut lock_surface = Self {
             surface,
             layer_surface,
             next_render_event,
             pools,
             dimensions: (0, 0),
             chars_entered: 0,
+            highlight: None,
+            highlight_ends: None,
+            failed_attempts: 0,
             redraw: false,
             state: LockState::Init,
-            config
-        }
+            mapped: false,
+            config,
+            safe_mode,
+            reload_error: None,
+            background: None,
+            screenshot,
+            slide: None,
+            fade: None,
+            animation: None,
+            frames: Vec::new(),
+            frame_pending: Rc::new(Cell::new(false)),
+        };
+        lock_surface.start_animation();
+        Ok(lock_surface)
     }
 
     /// Set the color of the surface. Will not take effect until handle_events() is called.
@@ -151,10 +220,108 @@ impl LockSurface {
         self.redraw = true
     }
 
+    /// Set the length of the password, highlighting whether it grew or shrank. Call after
+    /// `set_state`.
     pub fn chars_entered(&mut self, num: u32) {
+        let highlight = match num.cmp(&self.chars_entered) {
+            Ordering::Greater => Some(Highlight::Key),
+            Ordering::Less if self.state != LockState::Fail => Some(Highlight::Backspace),
+            Ordering::Less => None,
+            Ordering::Equal => return,
+        };
+        let start = self.highlight.map_or(0.0, |(_, start)| (start + HIGHLIGHT_STEP) % TAU);
+        self.highlight = highlight.map(|highlight| (highlight, start));
+        self.highlight_ends = highlight.map(|_| Instant::now() + HIGHLIGHT_DURATION);
         self.chars_entered = num;
     }
 
+    pub fn set_failed_attempts(&mut self, num: u32) {
+        self.failed_attempts = num;
+    }
+
+    /// Replace the configuration used for drawing. Will not take effect until the next redraw.
+    pub fn set_config(&mut self, config: Arc<Config>) {
+        self.config = config;
+        self.background = None;
+        self.start_animation();
+    }
+
+    /// Play the background image from its first frame if it is animated and shown.
+    fn start_animation(&mut self) {
+        let background = &self.config.background;
+        let animated = background.frames.len() > 1
+            && !self.shows_screenshot()
+            && !self.safe_mode
+            && !self.config.safe_mode;
+        self.animation = if animated {
+            let delays = background.frames.iter().map(|(_, delay)| *delay).collect();
+            Some(Animation::new(delays, background.pause_after, Instant::now()))
+        } else {
+            None
+        };
+        self.frames = vec![None; if animated { background.frames.len() } else { 0 }];
+    }
+
+    /// Play the animated background again if it paused.
+    pub fn resume_animation(&mut self) {
+        if let Some(animation) = &mut self.animation {
+            let now = Instant::now();
+            if animation.paused(now) {
+                animation.resume(now);
+                self.redraw = true;
+            }
+        }
+    }
+
+    /// How long until the next frame of the animated background should be drawn. `None` if
+    /// there is none, or it waits for the compositor.
+    pub fn until_next_frame(&self) -> Option<Duration> {
+        if self.frame_pending.get() {
+            return None;
+        }
+        self.animation.as_ref().and_then(|animation| animation.until_next(Instant::now()))
+    }
+
+    /// Whether the surface covers its output.
+    pub fn is_mapped(&self) -> bool {
+        self.mapped
+    }
+
+    /// How long until the highlight of the last keypress or backspace is cleared.
+    pub fn until_highlight_ends(&self) -> Option<Duration> {
+        self.highlight_ends.map(|ends| ends.saturating_duration_since(Instant::now()))
+    }
+
+    /// Show why reloading the configuration failed in a banner, or hide it again with `None`.
+    pub fn set_reload_error(&mut self, error: Option<String>) {
+        self.reload_error = error;
+    }
+
+    /// Show the next image of the slideshow, fading over from the current background.
+    pub fn set_slide(&mut self, image: RgbaImage) {
+        if let Some((dimensions, frame)) = self.background.take() {
+            if self.slide.is_some() && !self.config.background.fade.is_zero() {
+                self.fade = Some(Fade { started: Instant::now(), dimensions, frame });
+            }
+        }
+        self.slide = Some(image);
+        self.redraw = true;
+    }
+
+    /// Whether the screenshot of the output is shown, instead of any image.
+    pub fn shows_screenshot(&self) -> bool {
+        self.screenshot.is_some() && self.config.background.screenshot
+    }
+
+    /// Whether images are cross-fading, which needs a redraw for every frame.
+    pub fn fading(&self) -> bool {
+        self.fade.is_some()
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        self.dimensions
+    }
+
     /// Handles any events that have occurred since the last call, redrawing if needed.
     /// Returns true if the surface should be dropped.
     pub fn handle_events(&mut self) -> bool {
@@ -167,6 +334,17 @@ impl LockSurface {
             None => {}
         }
 
+        if let Some(animation) = &mut self.animation {
+            if !self.frame_pending.get() && animation.advance(Instant::now()) {
+                self.redraw = true;
+            }
+        }
+
+        if self.highlight_ends.is_some_and(|ends| ends <= Instant::now()) {
+            self.highlight_ends = None;
+            self.redraw = true;
+        }
+
         if self.redraw {
             match self.redraw() {
                 Ok(()) => {}
@@ -181,25 +359,144 @@ impl LockSurface {
 
     /// Attempt to redraw the surface using the current color
     fn redraw(&mut self) -> Result<(), DrawError> {
-        let pool = self.pools.pool().map_or(Err(DrawError::NoFreePool), Ok)?;
+        let safe_mode = self.safe_mode || self.config.safe_mode;
+        if !safe_mode {
+            let (config, dimensions) = (&self.config, self.dimensions);
+            // Every frame is kept at the size of the output, too large animations are shown still
+            let frames = config.background.frames.len();
+            if self.animation.is_some()
+                && frames * 4 * dimensions.0 * dimensions.1 > MAX_ANIMATION_BYTES
+            {
+                log::warn!(
+                    "The {} frames of the animation take more than {} MiB at {}x{}, showing the \
+                     first frame only",
+                    frames,
+                    MAX_ANIMATION_BYTES >> 20,
+                    dimensions.0,
+                    dimensions.1
+                );
+                self.animation = None;
+                self.frames = Vec::new();
+            }
+            // The screenshot is stretched, as outputs with a scale are captured in physical pixels
+            let screenshot = self.screenshot.as_ref().filter(|_| config.background.screenshot);
+            let slide = self.slide.as_ref().map(|slide| (slide, config.background.mode));
+            let image = screenshot.map(|screenshot| (screenshot, ScaleMode::Stretch)).or(slide);
+            match &self.animation {
+                Some(animation) => {
+                    let frame = animation.frame();
+                    let image = (&config.background.frames[frame].0, config.background.mode);
+                    let cache = &mut self.frames[frame];
+                    Self::render_background(cache, config, Some(image), dimensions);
+                }
+                None => Self::render_background(&mut self.background, config, image, dimensions),
+            }
+        }
+        let fade = self.config.background.fade;
+        if self.fade.as_ref().is_some_and(|from| {
+            from.started.elapsed() >= fade || from.dimensions != self.dimensions || safe_mode
+        }) {
+            self.fade = None;
+        }
+
+        let pool = self.pools.pool().ok_or(DrawError::NoFreePool)?;
 
         let stride = 4 * self.dimensions.0;
         let width = self.dimensions.0;
         let height = self.dimensions.1;
 
         // First make sure the pool is large enough
-        pool.resize((stride * height) as usize)?;
+        pool.resize(stride * height)?;
 
         // Create a new buffer from the pool
         let buffer =
             pool.buffer(0, width as i32, height as i32, stride as i32, wl_shm::Format::Argb8888);
 
-        let font = &self.config.font;
+        let ptr = pool.mmap().as_mut_ptr();
+        let mut canvas = Canvas {
+            mem: ptr,
+            dimensions: (width, height),
+            color: self.state.map_to_color(self.config.clone()),
+            fonts: &self.config.font,
+        };
 
-        let ptr = pool.mmap().as_mut_ptr() as *mut u8;
-        let mut canvas =
-            Canvas { mem: ptr, dimensions: (width, height), color: self.state.map_to_color(self.config.clone()), fonts: font };
+        if safe_mode {
+            // Only show the state color, nothing that could fail while rendering text
+            canvas.fill();
+        } else {
+            self.draw_ui(&mut canvas);
+        }
+        if let Some(error) = &self.reload_error {
+            self.draw_banner(&mut canvas, error, !safe_mode);
+        }
+
+        // Attach the buffer to the surface and mark the entire surface as damaged
+        self.surface.attach(Some(&buffer), 0, 0);
+        self.surface.damage_buffer(0, 0, width as i32, height as i32);
+
+        // Ask to be told when the compositor wants the next frame of the animation
+        if self.animation.as_ref().is_some_and(|animation| !animation.paused(Instant::now())) {
+            let frame_pending = Rc::clone(&self.frame_pending);
+            frame_pending.set(true);
+            self.surface.frame().quick_assign(move |_, _, _| frame_pending.set(false));
+        }
 
+        // Finally, commit the surface
+        self.surface.commit();
+        self.mapped = true;
+
+        Ok(())
+    }
+
+    /// Render the background for `dimensions` with `image`, the screenshot or the slide, else the
+    /// configured image, unless it is already cached. A plain `color_bg` needs no frame.
+    fn render_background(
+        cache: &mut Rendered,
+        config: &Config,
+        image: Option<(&RgbaImage, ScaleMode)>,
+        dimensions: (usize, usize),
+    ) {
+        let background = &config.background;
+        let image = match (image, &background.image) {
+            (Some(image), _) => Some(image),
+            (None, Some(image)) => Some((image, background.mode)),
+            _ if background.is_plain() => return,
+            _ => None,
+        };
+        if !matches!(cache, Some((cached, _)) if *cached == dimensions) {
+            let color = config.colors.bg_color;
+            let frame = background::render(background, image, color, dimensions);
+            *cache = Some((dimensions, frame));
+        }
+    }
+
+    /// Draw the full UI: background, clock, user and password feedback.
+    fn draw_ui(&self, canvas: &mut Canvas) {
+        let background = match &self.animation {
+            Some(animation) => &self.frames[animation.frame()],
+            None => &self.background,
+        };
+        match background {
+            Some((dimensions, background)) => canvas.blit(background, *dimensions, (0, 0)),
+            None => {
+                canvas.color = self.config.colors.bg_color;
+                canvas.fill();
+            }
+        }
+        if let Some(from) = &self.fade {
+            let duration = self.config.background.fade.as_secs_f64();
+            let progress = from.started.elapsed().as_secs_f64() / duration;
+            canvas.blend_frame(&from.frame, ((1.0 - progress).max(0.0) * 255.0) as u8);
+        }
+        match self.config.indicator.style {
+            IndicatorStyle::Bar => self.draw_bar(canvas),
+            IndicatorStyle::Ring => self.draw_ring(canvas),
+        }
+    }
+
+    /// Draw the text centered on the output, with a bar in the state color below it.
+    fn draw_bar(&self, canvas: &mut Canvas) {
+        let (width, height) = self.dimensions;
         let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
         layout.reset(&LayoutSettings {
             max_width: Some(self.dimensions.0 as f32),
@@ -209,33 +506,127 @@ impl LockSurface {
             .. LayoutSettings::default()
         });
 
-        let text = {
-            let time = chrono::prelude::Local::now();
-            format!("{:02}:{:02}\n", time.hour(), time.minute())
-        };
-
-        layout.append(font, &TextStyle::new(&text, 64.0, 0));
+        let chains = &self.config.font_chains;
+        canvas.append_text(&mut layout, &format!("{}\n", clock()), 64.0, &chains.clock);
 
-        let text = format!("User: {}\n", self.config.user);
-        layout.append(font, &TextStyle::new(&text, 32.0, 0));
+        if self.config.show_user {
+            let text = format!("User: {}\n", self.config.user);
+            canvas.append_text(&mut layout, &text, 32.0, &chains.user);
+        }
         let text = format!("pwd: {}", "*".to_string().repeat(min(self.chars_entered, 64) as usize));
-        layout.append(font, &TextStyle::new(&text, 32.0, 0));
+        canvas.append_text(&mut layout, &text, 32.0, &chains.password);
+        if let Some(message) = &self.config.message {
+            canvas.append_text(&mut layout, &format!("\n{}", message), 24.0, &chains.message);
+        }
+        if self.config.show_failed_attempts && self.failed_attempts > 0 {
+            let text = format!("\n{} failed attempt(s)", self.failed_attempts);
+            canvas.append_text(&mut layout, &text, 24.0, &chains.password);
+        }
 
-        canvas.color = self.config.colors.bg_color;
-        canvas.fill();
         canvas.color = self.config.colors.text_color;
         canvas.draw_layout(&mut layout);
         canvas.color = self.state.map_to_color(self.config.clone());
-        canvas.draw_square((450, height / 2 + 50), (width - 450, height / 2 + 60));
+        // Outputs narrower than the margins have no room for the bar
+        let (left, right) = (450, width.saturating_sub(450));
+        if left < right {
+            canvas.draw_square((left, height / 2 + 50), (right, min(height / 2 + 60, height)));
+        }
+    }
 
-        // Attach the buffer to the surface and mark the entire surface as damaged
-        self.surface.attach(Some(&buffer), 0, 0);
-        self.surface.damage_buffer(0, 0, width as i32, height as i32);
+    /// Draw a ring in the state color with the clock inside, and the rest of the text below it.
+    /// The last keypress or backspace is highlighted as an arc of the ring.
+    fn draw_ring(&self, canvas: &mut Canvas) {
+        let (width, height) = self.dimensions;
+        let indicator = &self.config.indicator;
+        let (radius, thickness) = (indicator.radius as f64, indicator.thickness as f64);
+        // Keep the whole ring on the output, so the text inside it is too
+        let extent = radius + thickness / 2.0;
+        let place = |position: Option<u32>, size: usize| {
+            let position = position.map_or(size as f64 / 2.0, f64::from);
+            position.min(size as f64 - extent).max(extent)
+        };
+        let center = (place(indicator.x, width), place(indicator.y, height));
 
-        // Finally, commit the surface
-        self.surface.commit();
+        canvas.color = self.state.map_to_color(self.config.clone());
+        canvas.stroke_circle(center, radius, thickness);
+        if let (Some((highlight, start)), Some(_)) = (self.highlight, self.highlight_ends) {
+            canvas.color = match highlight {
+                Highlight::Key => self.config.colors.key_highlight_color,
+                Highlight::Backspace => self.config.colors.backspace_highlight_color,
+            };
+            canvas.stroke_arc(center, radius, thickness, start, HIGHLIGHT_SWEEP);
+        }
 
-        Ok(())
+        let chains = &self.config.font_chains;
+        let inner = (radius - thickness / 2.0).max(0.0);
+        let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
+        layout.reset(&LayoutSettings {
+            x: (center.0 - inner) as f32,
+            y: (center.1 - inner) as f32,
+            max_width: Some(2.0 * inner as f32),
+            max_height: Some(2.0 * inner as f32),
+            horizontal_align: HorizontalAlign::Center,
+            vertical_align: VerticalAlign::Middle,
+            .. LayoutSettings::default()
+        });
+        let px = inner as f32 / 2.0;
+        canvas.append_text(&mut layout, &clock(), px, &chains.clock);
+        if self.state == LockState::Fail {
+            canvas.append_text(&mut layout, "\nWrong", px * 0.6, &chains.password);
+        }
+        canvas.color = self.config.colors.text_color;
+        canvas.draw_layout(&mut layout);
+
+        // Below the ring, as far as the output reaches
+        let top = (center.1 + extent + 16.0) as f32;
+        layout.reset(&LayoutSettings {
+            y: top,
+            max_width: Some(width as f32),
+            max_height: Some((height as f32 - top).max(0.0)),
+            horizontal_align: HorizontalAlign::Center,
+            .. LayoutSettings::default()
+        });
+        if self.config.show_user {
+            let text = format!("User: {}\n", self.config.user);
+            canvas.append_text(&mut layout, &text, 32.0, &chains.user);
+        }
+        if let Some(message) = &self.config.message {
+            canvas.append_text(&mut layout, &format!("{}\n", message), 24.0, &chains.message);
+        }
+        if self.config.show_failed_attempts && self.failed_attempts > 0 {
+            let text = format!("{} failed attempt(s)", self.failed_attempts);
+            canvas.append_text(&mut layout, &text, 24.0, &chains.password);
+        }
+        canvas.draw_layout(&mut layout);
+    }
+
+    /// Draw a banner along the top edge, telling that the configuration could not be reloaded.
+    /// Without text the banner is just a strip in the failure color.
+    fn draw_banner(&self, canvas: &mut Canvas, error: &str, with_text: bool) {
+        let (width, height) = self.dimensions;
+        let banner_height = min(BANNER_HEIGHT, height);
+
+        canvas.color = self.config.colors.fail_color;
+        canvas.draw_square((0, 0), (width, banner_height));
+        if !with_text || self.config.font.is_empty() {
+            return;
+        }
+
+        let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
+        layout.reset(&LayoutSettings {
+            max_width: Some(width as f32),
+            max_height: Some(banner_height as f32),
+            horizontal_align: HorizontalAlign::Center,
+            vertical_align: VerticalAlign::Middle,
+            .. LayoutSettings::default()
+        });
+        // Keep to a single line, so the text stays within the banner
+        let error: String = error.lines().next().unwrap_or_default().chars().take(120).collect();
+        let text = format!("Configuration not reloaded: {}", error);
+        canvas.append_text(&mut layout, &text, 16.0, &self.config.font_chains.banner);
+
+        canvas.color = self.config.colors.text_color;
+        canvas.draw_layout(&mut layout);
     }
 }
 
@@ -245,3 +636,9 @@ impl Drop for LockSurface {
         self.surface.destroy();
     }
 }
+
+/// The time of day, as shown by the clock.
+fn clock() -> String {
+    let time = chrono::prelude::Local::now();
+    format!("{:02}:{:02}", time.hour(), time.minute())
+}
@@ -1,59 +1,100 @@
-use std::collections::VecDeque;
+use std::convert::TryFrom;
 use std::io;
 use std::process::exit;
 
-use log::error;
-use nix::sys::wait::{waitpid, WaitStatus};
-use nix::unistd::{fork, ForkResult};
+use log::{error, info};
+use nix::sys::signal::{kill, Signal};
 
-use config::Config;
+use config::{check_config, import_swaylock, warn_unknown, Command, Config, ConfigError, Options};
 
-use crate::lock::lock_screen;
+use crate::instance::{Acquire, InstanceLock};
+use crate::lock::diagnose;
+use crate::supervisor::{exit_code, supervise};
 use std::sync::Arc;
-use std::time::Instant;
 
 mod config;
+mod instance;
 mod lock;
 mod logger;
+mod supervisor;
 
-fn main() -> io::Result<()> {
-    let options = match Config::new() {
-        Ok(config) => Arc::new(config),
+fn config_error(err: ConfigError) -> ! {
+    eprintln!("{}", err);
+    error!("{:?}", err);
+    exit(exit_code::CONFIG_ERROR);
+}
+
+/// Ask the running supervisor to reload its configuration, it passes the request on to the lock.
+fn reload() -> i32 {
+    match InstanceLock::running() {
+        Ok(Some(pid)) => match kill(pid, Signal::SIGHUP) {
+            Ok(()) => 0,
+            Err(errno) => {
+                eprintln!("Could not signal waylock (pid {}): {}", pid, errno);
+                exit_code::NOT_RUNNING
+            }
+        },
+        Ok(None) => {
+            eprintln!("waylock is not running.");
+            exit_code::NOT_RUNNING
+        }
         Err(err) => {
-            eprintln!("{}", err);
-            error!("{:?}", err);
-            exit(1);
+            eprintln!("Could not read the instance lock: {}", err);
+            exit_code::NOT_RUNNING
         }
-    };
+    }
+}
 
-    let mut restarts: VecDeque<Instant> = VecDeque::with_capacity(options.max_restarts);
-
-    loop {
-        match unsafe { fork() } {
-            Ok(ForkResult::Child) => match lock_screen(options) {
-                Ok(()) => exit(0),
-                Err(err) => {
-                    error!("[MAIN] lock_screen error: {:?}", err);
-                    exit(1);
-                }
-            },
-            Ok(ForkResult::Parent { child }) => match waitpid(child, None) {
-                Ok(WaitStatus::Exited(_pid, code)) if code <= 1 => exit(0),
-                a => {
-                    error!("[MAIN] waitpid() didn't behave as expected. Code: {:?}", a);
-                    if restarts.len() == options.max_restarts {
-                        match restarts.pop_front() {
-                            Some(ts) if ts.elapsed().as_secs() < 1 => exit(1),
-                            _ => {}
-                        }
-                    }
-                    restarts.push_back(Instant::now())
-                },
-            },
-            Err(errno) => {
-                error!("[MAIN] couldn't fork(). ERRNO: {}", errno);
-                exit(1);
+fn main() -> io::Result<()> {
+    let cmd_params = Options::parse_args().unwrap_or_else(|err| config_error(err));
+    // Checking a config file must not fail on the very problems it is supposed to report.
+    match cmd_params.command {
+        Some(Command::CheckConfig { file }) => exit(check_config(file)),
+        Some(Command::Reload) => exit(reload()),
+        Some(Command::ImportSwaylock { file }) => exit(import_swaylock(file)),
+        _ => {}
+    }
+
+    let options =
+        Options::layered(cmd_params, &mut warn_unknown).unwrap_or_else(|err| config_error(err));
+
+    if let Some(Command::PrintConfig) = options.command {
+        match options.to_toml() {
+            Ok(config) => {
+                print!("{}", config);
+                exit(0);
+            }
+            Err(err) => {
+                eprintln!("Could not serialize the configuration: {}", err);
+                exit(exit_code::CRASH);
             }
         }
     }
+
+    if options.diagnose {
+        let json = options.json;
+        let config = Config::try_from(options.clone()).unwrap_or_else(|err| config_error(err));
+        exit(diagnose(options, &config, json));
+    }
+
+    let options = Config::try_from(options).unwrap_or_else(|err| config_error(err));
+    let options = Arc::new(options);
+
+    let instance = match InstanceLock::acquire() {
+        Ok(Acquire::Acquired(instance)) => Some(instance),
+        Ok(Acquire::Running(pid)) => {
+            info!("[MAIN] waylock is already running (pid {:?}), not locking again.", pid);
+            exit(0);
+        }
+        Err(err) => {
+            error!("[MAIN] Could not acquire the instance lock, continuing without it: {}", err);
+            None
+        }
+    };
+
+    let code = supervise(options);
+    if let Some(instance) = instance {
+        instance.release();
+    }
+    exit(code)
 }
@@ -0,0 +1,280 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::io;
+use std::process::{exit, Command};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+use nix::sys::signal::{self, kill};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::fcntl::OFlag;
+use nix::libc;
+use nix::unistd::{close, fork, getpid, getppid, pipe2, read, setpgid, write, ForkResult, Pid};
+use smithay_client_toolkit::reexports::calloop::{
+    self,
+    generic::Generic,
+    signals::{Signal, Signals},
+    Interest, LoopHandle, Mode, Source,
+};
+
+use crate::config::{Config, ConfigWatcher};
+use crate::lock::lock_screen;
+
+/// Exit codes of waylock. The lock child reports its result through them as well, every other
+/// exit status of the child is considered a crash.
+pub mod exit_code {
+    pub const UNLOCKED: i32 = 0;
+    pub const CONFIG_ERROR: i32 = 2;
+    pub const COMPOSITOR_GONE: i32 = 3;
+    pub const CRASH: i32 = 4;
+    pub const MISSING_GLOBAL: i32 = 5;
+    pub const CONNECTION_FAILED: i32 = 6;
+    pub const SHM_FAILED: i32 = 7;
+    pub const AUTH_FAILED: i32 = 8;
+    pub const NOT_RUNNING: i32 = 9;
+
+    /// Whether restarting the lock child can't help after it exited with this code. Only asked
+    /// until the screen was locked, from then on every failure is handled like a crash.
+    pub fn is_fatal(code: i32) -> bool {
+        matches!(code, CONFIG_ERROR | COMPOSITOR_GONE)
+    }
+}
+
+/// Signals handled by both the supervisor and the lock child.
+pub const SIGNALS: &[Signal] =
+    &[Signal::SIGTERM, Signal::SIGINT, Signal::SIGHUP, Signal::SIGUSR1, Signal::SIGUSR2];
+
+struct Supervisor {
+    config: Arc<Config>,
+    child: Option<Pid>,
+    status: Option<nix::Result<WaitStatus>>,
+    restarts: VecDeque<Instant>,
+    escalated: bool,
+    /// Whether a lock child ever covered an output. Giving up after that would unlock the screen.
+    locked: bool,
+    handle: LoopHandle<Supervisor>,
+    watcher: Option<Source<Generic<ConfigWatcher>>>,
+}
+
+impl Supervisor {
+    fn handle_signal(&mut self, signal: Signal) {
+        match signal {
+            Signal::SIGCHLD => return self.reap_child(),
+            Signal::SIGHUP => match Config::new() {
+                Ok(config) => {
+                    info!("[MAIN] Reloaded the configuration.");
+                    self.config = Arc::new(config);
+                    // Editors replace the file, and the reloaded config may come from other files.
+                    // The watcher may be the source being dispatched, so it's replaced afterwards.
+                    self.handle.insert_idle(Supervisor::watch_config);
+                }
+                Err(err) => warn!("[MAIN] Keeping the old configuration: {}", err),
+            },
+            Signal::SIGUSR2 => info!(
+                "[MAIN] Supervising child {:?}, {} recent restart(s), escalated: {}.",
+                self.child,
+                self.restarts.len(),
+                self.escalated
+            ),
+            _ => {}
+        }
+
+        // The child decides whether a signal unlocks the screen, so every signal is forwarded.
+        if let Some(child) = self.child {
+            let forwarded = signal::Signal::try_from(signal as i32).map(|sig| kill(child, sig));
+            if let Ok(Err(errno)) | Err(errno) = forwarded {
+                warn!("[MAIN] Could not forward {:?} to child {}: {}", signal, child, errno);
+            }
+        }
+    }
+
+    /// Watch the files of the current configuration, replacing the previous watcher. A changed
+    /// config file is handled just like SIGHUP, the lock child is told to reload as well.
+    fn watch_config(&mut self) {
+        if let Some(watcher) = self.watcher.take() {
+            self.handle.remove(watcher);
+        }
+        let handle = &self.handle;
+        let watched = ConfigWatcher::new(&self.config.config_files)
+            .map_err(io::Error::from)
+            .and_then(|watcher| {
+                handle
+                    .insert_source(
+                        Generic::new(watcher, Interest::Readable, Mode::Level),
+                        |_, watcher, supervisor| {
+                            if watcher.changed() {
+                                info!("[MAIN] The configuration file changed, reloading.");
+                                supervisor.handle_signal(Signal::SIGHUP);
+                            }
+                            Ok(())
+                        },
+                    )
+                    .map_err(|err| err.error)
+            });
+        match watched {
+            Ok(watcher) => self.watcher = Some(watcher),
+            Err(err) => warn!("[MAIN] Could not watch the configuration for changes: {}", err),
+        }
+    }
+
+    fn reap_child(&mut self) {
+        if let Some(child) = self.child {
+            match waitpid(child, Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::StillAlive) => {}
+                status => {
+                    self.child = None;
+                    self.status = Some(status);
+                }
+            }
+        }
+    }
+
+    /// Record a crash of the child and return the delay before it should be restarted.
+    fn record_crash(&mut self) -> Duration {
+        let now = Instant::now();
+        let window = self.config.restart_window;
+        while matches!(self.restarts.front(), Some(ts) if now.duration_since(*ts) > window) {
+            self.restarts.pop_front();
+        }
+        self.restarts.push_back(now);
+
+        // A limit of 0 restarts endlessly without ever escalating.
+        if self.config.max_restarts != 0 && self.restarts.len() > self.config.max_restarts {
+            self.escalate();
+            self.restarts.clear();
+        }
+
+        self.config.restart_backoff * self.restarts.len() as u32
+    }
+
+    /// Called once the crash limit is hit. Giving up would unlock the screen, so the lock keeps
+    /// being restarted in safe mode after running the crash hook.
+    fn escalate(&mut self) {
+        error!(
+            "[MAIN] The lock crashed more than {} times within {:?}, falling back to safe mode.",
+            self.config.max_restarts, self.config.restart_window
+        );
+        self.escalated = true;
+
+        if let Some(command) = &self.config.crash_command {
+            if let Err(err) = Command::new("sh").arg("-c").arg(command).spawn() {
+                warn!("[MAIN] Error executing crash command \"{}\": {}", command, err);
+            }
+        }
+    }
+}
+
+/// Fork the lock child and restart it if it crashes. Returns the exit code once the screen is
+/// unlocked, or the lock child failed in a way restarting can't fix.
+pub fn supervise(config: Arc<Config>) -> i32 {
+    // Signals have to be blocked before forking, so none are lost before the child listens itself.
+    let mut event_loop = calloop::EventLoop::<Supervisor>::new().unwrap_or_else(|err| {
+        error!("[MAIN] Could not create the event loop: {}", err);
+        exit(exit_code::CRASH);
+    });
+    let mut signals = [Signal::SIGCHLD].to_vec();
+    signals.extend_from_slice(SIGNALS);
+    let inserted = Signals::new(&signals).and_then(|signals| {
+        event_loop
+            .handle()
+            .insert_source(signals, |event, _, supervisor| supervisor.handle_signal(event.signal()))
+            .map_err(|err| err.error)
+    });
+    // Without a signal source, fall back to polling for the child exit.
+    let timeout = match inserted {
+        Ok(_) => None,
+        Err(err) => {
+            error!("[MAIN] Could not listen for signals: {}", err);
+            Some(Duration::from_secs(1))
+        }
+    };
+
+    let mut supervisor = Supervisor {
+        config,
+        child: None,
+        status: None,
+        restarts: VecDeque::new(),
+        escalated: false,
+        locked: false,
+        handle: event_loop.handle(),
+        watcher: None,
+    };
+    supervisor.watch_config();
+
+    loop {
+        // The child writes a byte once it covered an output
+        let (locked_rx, locked_tx) = pipe2(OFlag::O_CLOEXEC | OFlag::O_NONBLOCK)
+            .unwrap_or_else(|errno| {
+                error!("[MAIN] Could not create a pipe: {}", errno);
+                exit(exit_code::CRASH);
+            });
+        let supervisor_pid = getpid();
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                // Leave the process group, so signals sent to it only reach us once, through the
+                // supervisor.
+                let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+                // A killed supervisor can't forward signals anymore, so its death is one. If it
+                // died before this was set up, the signal is sent right away.
+                unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM) };
+                if getppid() != supervisor_pid {
+                    let _ = signal::raise(signal::Signal::SIGTERM);
+                }
+                let _ = close(locked_rx);
+                let safe_mode = supervisor.config.safe_mode || supervisor.escalated;
+                let on_locked = move || {
+                    let _ = write(locked_tx, &[1]);
+                };
+                match lock_screen(supervisor.config.clone(), safe_mode, on_locked) {
+                    Ok(()) => exit(exit_code::UNLOCKED),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        error!("[MAIN] lock_screen error: {:?}", err);
+                        exit(err.exit_code());
+                    }
+                }
+            }
+            Ok(ForkResult::Parent { child }) => {
+                supervisor.child = Some(child);
+                let _ = close(locked_tx);
+            }
+            Err(errno) => {
+                error!("[MAIN] couldn't fork(). ERRNO: {}", errno);
+                exit(exit_code::CRASH);
+            }
+        }
+
+        while supervisor.status.is_none() {
+            if let Err(err) = event_loop.dispatch(timeout, &mut supervisor) {
+                error!("[MAIN] Event loop error: {}", err);
+            }
+            supervisor.reap_child();
+        }
+        supervisor.locked |= read(locked_rx, &mut [0]) == Ok(1);
+        let _ = close(locked_rx);
+
+        match supervisor.status.take() {
+            Some(Ok(WaitStatus::Exited(_pid, exit_code::UNLOCKED))) => return exit_code::UNLOCKED,
+            Some(Ok(WaitStatus::Exited(_pid, code)))
+                if exit_code::is_fatal(code) && !supervisor.locked =>
+            {
+                error!("[MAIN] The lock can't be restarted. Code: {}", code);
+                return code;
+            }
+            status => {
+                error!("[MAIN] The lock crashed. Status: {:?}", status);
+                let deadline = Instant::now() + supervisor.record_crash();
+                // Keep handling signals while backing off
+                while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+                    if left == Duration::from_secs(0) {
+                        break;
+                    }
+                    if let Err(err) = event_loop.dispatch(left, &mut supervisor) {
+                        error!("[MAIN] Event loop error: {}", err);
+                    }
+                }
+            }
+        }
+    }
+}